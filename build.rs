@@ -4,7 +4,7 @@ use std::process::Command;
 fn main() {
     println!("cargo:rerun-if-changed=build.rs");
     println!("cargo:rerun-if-changed=go.sum");
-    println!("cargo:rerun-if-changed=go-lib/lassie.go");
+    println!("cargo:rerun-if-changed=go-lib");
 
     let v = get_lassie_version();
     // assert_eq!(
@@ -40,7 +40,7 @@ fn build_lassie() {
             "-o",
             out_file,
             "-buildmode=c-archive",
-            "lassie-ffi.go",
+            ".",
         ])
         .env("GOARCH", goarch)
         // We must explicitly enable CGO when cross-compiling
@@ -103,7 +103,7 @@ fn build_lassie() {
             "-o",
             &out_file,
             "-buildmode=c-shared",
-            "lassie-ffi.go",
+            ".",
         ])
         .status()
         .expect(
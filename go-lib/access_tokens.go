@@ -0,0 +1,251 @@
+package main
+
+import (
+	"crypto/subtle"
+	"math"
+	"net/http"
+	"strconv"
+	"sync"
+	"sync/atomic"
+	"time"
+
+	httpserver "github.com/filecoin-project/lassie/pkg/server/http"
+	"github.com/filecoin-project/lassie/pkg/types"
+)
+
+// accessToken is a token accepted by the HTTP server together with the limits applied to the
+// requests it authorizes. Zero limits are not enforced.
+type accessToken struct {
+	value             string
+	maxConcurrent     uint32
+	requestsPerMinute uint32
+	bytesPerDay       uint64
+	// maxBlocks overrides the block limit of the daemon when not zero
+	maxBlocks uint64
+}
+
+// accessTokens authorizes `/ipfs/` requests using the configured tokens and enforces the limits of
+// each token. The tokens can be replaced while the server is running, requests that were already
+// authorized are not affected.
+type accessTokens struct {
+	fetcher   types.Fetcher
+	tempDir   string
+	maxBlocks uint64
+
+	// anonymous serves the requests when no tokens are configured
+	anonymous http.Handler
+	entries   atomic.Pointer[[]*tokenEntry]
+
+	// usageMtx protects `usage`. The usage of tokens that are kept when the tokens are replaced is
+	// preserved, so that rotating other tokens does not reset the quotas.
+	usageMtx sync.Mutex
+	usage    map[string]*tokenUsage
+}
+
+type tokenEntry struct {
+	token accessToken
+	// expected is the value of the Authorization header sent with this token
+	expected []byte
+	// handler serves the requests using the block limit of this token
+	handler http.Handler
+	usage   *tokenUsage
+}
+
+func newAccessTokens(fetcher types.Fetcher, tempDir string, maxBlocks uint64, tokens []accessToken) *accessTokens {
+	a := &accessTokens{
+		fetcher:   fetcher,
+		tempDir:   tempDir,
+		maxBlocks: maxBlocks,
+		usage:     map[string]*tokenUsage{},
+	}
+	a.anonymous = a.newIpfsHandler(0)
+	a.set(tokens)
+	return a
+}
+
+func (a *accessTokens) newIpfsHandler(maxBlocks uint64) http.Handler {
+	if maxBlocks == 0 {
+		maxBlocks = a.maxBlocks
+	}
+	return http.HandlerFunc(httpserver.IpfsHandler(a.fetcher, httpserver.HttpServerConfig{
+		TempDir:             a.tempDir,
+		MaxBlocksPerRequest: maxBlocks,
+	}))
+}
+
+// set replaces the accepted tokens. An empty list disables the auth check.
+func (a *accessTokens) set(tokens []accessToken) {
+	a.usageMtx.Lock()
+	defer a.usageMtx.Unlock()
+
+	entries := make([]*tokenEntry, 0, len(tokens))
+	usage := make(map[string]*tokenUsage, len(tokens))
+	for _, token := range tokens {
+		u := a.usage[token.value]
+		if u == nil {
+			u = &tokenUsage{}
+		}
+		usage[token.value] = u
+		entries = append(entries, &tokenEntry{
+			token:    token,
+			expected: []byte("Bearer " + token.value),
+			handler:  a.newIpfsHandler(token.maxBlocks),
+			usage:    u,
+		})
+	}
+	a.usage = usage
+	a.entries.Store(&entries)
+}
+
+// getUsage returns the usage counters of the token, or false when the token is not configured.
+func (a *accessTokens) getUsage(value string) (tokenUsageSnapshot, bool) {
+	a.usageMtx.Lock()
+	u := a.usage[value]
+	a.usageMtx.Unlock()
+
+	if u == nil {
+		return tokenUsageSnapshot{}, false
+	}
+	return u.snapshot(time.Now()), true
+}
+
+func (a *accessTokens) ServeHTTP(res http.ResponseWriter, req *http.Request) {
+	entries := *a.entries.Load()
+	if len(entries) == 0 {
+		a.anonymous.ServeHTTP(res, req)
+		return
+	}
+
+	actual := []byte(req.Header.Get("Authorization"))
+	var entry *tokenEntry
+	// Compare with all tokens to avoid leaking which token matched via timing
+	for _, e := range entries {
+		if subtle.ConstantTimeCompare(actual, e.expected) == 1 {
+			entry = e
+		}
+	}
+	if entry == nil {
+		http.Error(res, "Unauthorized", http.StatusUnauthorized)
+		return
+	}
+
+	if retryAfter, ok := entry.usage.admit(entry.token, time.Now()); !ok {
+		seconds := int(math.Ceil(retryAfter.Seconds()))
+		if seconds < 1 {
+			seconds = 1
+		}
+		res.Header().Set("Retry-After", strconv.Itoa(seconds))
+		http.Error(res, "Too Many Requests", http.StatusTooManyRequests)
+		return
+	}
+	defer entry.usage.finish()
+
+	entry.handler.ServeHTTP(&countingWriter{ResponseWriter: res, usage: entry.usage}, req)
+}
+
+// tokenUsage tracks the resources consumed by the requests authorized by one token.
+type tokenUsage struct {
+	mtx    sync.Mutex
+	active uint32
+	// requests holds the start times of the requests admitted in the last minute, oldest first
+	requests []time.Time
+	// day is the start of the UTC day the bytes are counted for
+	day   time.Time
+	bytes uint64
+}
+
+type tokenUsageSnapshot struct {
+	active             uint32
+	requestsLastMinute uint32
+	bytesToday         uint64
+}
+
+// admit starts a request when the token is within its limits. Otherwise it returns the time after
+// which the request can be retried.
+func (u *tokenUsage) admit(token accessToken, now time.Time) (time.Duration, bool) {
+	u.mtx.Lock()
+	defer u.mtx.Unlock()
+	u.advance(now)
+
+	if token.maxConcurrent > 0 && u.active >= token.maxConcurrent {
+		// We cannot tell when one of the running requests finishes
+		return time.Second, false
+	}
+	if token.requestsPerMinute > 0 && uint32(len(u.requests)) >= token.requestsPerMinute {
+		return u.requests[0].Add(time.Minute).Sub(now), false
+	}
+	if token.bytesPerDay > 0 && u.bytes >= token.bytesPerDay {
+		return u.day.Add(24 * time.Hour).Sub(now), false
+	}
+
+	u.active++
+	u.requests = append(u.requests, now)
+	return 0, true
+}
+
+func (u *tokenUsage) finish() {
+	u.mtx.Lock()
+	defer u.mtx.Unlock()
+	u.active--
+}
+
+// addBytes counts bytes sent in responses. The daily limit is checked when requests start, a
+// response that is already running is not aborted when it crosses the limit.
+func (u *tokenUsage) addBytes(n int) {
+	u.mtx.Lock()
+	defer u.mtx.Unlock()
+	u.advance(time.Now())
+	u.bytes += uint64(n)
+}
+
+func (u *tokenUsage) snapshot(now time.Time) tokenUsageSnapshot {
+	u.mtx.Lock()
+	defer u.mtx.Unlock()
+	u.advance(now)
+	return tokenUsageSnapshot{
+		active:             u.active,
+		requestsLastMinute: uint32(len(u.requests)),
+		bytesToday:         u.bytes,
+	}
+}
+
+// advance drops requests older than one minute and resets the byte counter when a new UTC day
+// starts. The caller must hold the lock.
+func (u *tokenUsage) advance(now time.Time) {
+	cutoff := now.Add(-time.Minute)
+	expired := 0
+	for expired < len(u.requests) && !u.requests[expired].After(cutoff) {
+		expired++
+	}
+	u.requests = u.requests[expired:]
+
+	// The zero time is at UTC midnight, truncating yields the start of the UTC day
+	if day := now.UTC().Truncate(24 * time.Hour); !day.Equal(u.day) {
+		u.day = day
+		u.bytes = 0
+	}
+}
+
+// countingWriter counts the bytes of the response body towards the daily limit of the token.
+type countingWriter struct {
+	http.ResponseWriter
+	usage *tokenUsage
+}
+
+func (w *countingWriter) Write(data []byte) (int, error) {
+	n, err := w.ResponseWriter.Write(data)
+	w.usage.addBytes(n)
+	return n, err
+}
+
+// Flush keeps streaming responses working, the handler flushes the CAR stream as blocks arrive.
+func (w *countingWriter) Flush() {
+	if flusher, ok := w.ResponseWriter.(http.Flusher); ok {
+		flusher.Flush()
+	}
+}
+
+// Unwrap gives http.ResponseController access to the original writer.
+func (w *countingWriter) Unwrap() http.ResponseWriter {
+	return w.ResponseWriter
+}
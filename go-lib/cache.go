@@ -0,0 +1,326 @@
+package main
+
+import (
+	"bytes"
+	"container/list"
+	"context"
+	"errors"
+	"fmt"
+	"io"
+	"os"
+	"path/filepath"
+	"sort"
+	"sync"
+	"time"
+
+	"github.com/filecoin-project/lassie/pkg/types"
+	"github.com/ipfs/go-cid"
+	"github.com/ipld/go-ipld-prime/datamodel"
+	"github.com/ipld/go-ipld-prime/linking"
+	cidlink "github.com/ipld/go-ipld-prime/linking/cid"
+	"github.com/ipld/go-trustless-utils/traversal"
+)
+
+// blockCache is a persistent blockstore keeping one file per block in a directory. When the total
+// size of the blocks exceeds the limit, the least recently used blocks are removed.
+//
+// The access order is persisted via file modification times, so that it survives restarts.
+type blockCache struct {
+	dir     string
+	maxSize uint64
+
+	mtx     sync.Mutex
+	size    uint64
+	entries map[cid.Cid]*list.Element
+	// lru holds *cacheEntry values, the most recently used entry is at the front
+	lru *list.List
+}
+
+type cacheEntry struct {
+	cid  cid.Cid
+	size uint64
+}
+
+func openBlockCache(dir string, maxSize uint64) (*blockCache, error) {
+	if err := os.MkdirAll(dir, 0o755); err != nil {
+		return nil, err
+	}
+
+	files, err := os.ReadDir(dir)
+	if err != nil {
+		return nil, err
+	}
+
+	type existing struct {
+		entry   cacheEntry
+		modTime time.Time
+	}
+	var blocks []existing
+	for _, file := range files {
+		if !file.Type().IsRegular() {
+			continue
+		}
+		c, err := cid.Parse(file.Name())
+		if err != nil {
+			// Leftovers of interrupted writes and files we don't own
+			continue
+		}
+		info, err := file.Info()
+		if err != nil {
+			return nil, err
+		}
+		blocks = append(blocks, existing{cacheEntry{c, uint64(info.Size())}, info.ModTime()})
+	}
+	sort.Slice(blocks, func(i, j int) bool { return blocks[i].modTime.After(blocks[j].modTime) })
+
+	cache := &blockCache{
+		dir:     dir,
+		maxSize: maxSize,
+		entries: make(map[cid.Cid]*list.Element, len(blocks)),
+		lru:     list.New(),
+	}
+	for _, block := range blocks {
+		entry := block.entry
+		cache.entries[entry.cid] = cache.lru.PushBack(&entry)
+		cache.size += entry.size
+	}
+	cache.mtx.Lock()
+	cache.evict()
+	cache.mtx.Unlock()
+
+	debug("OPENED BLOCK CACHE", dir, "blocks:", len(cache.entries), "size:", cache.size)
+	return cache, nil
+}
+
+func (c *blockCache) path(key cid.Cid) string {
+	return filepath.Join(c.dir, key.String())
+}
+
+// peek returns the data of the block, or nil when the block is not cached. It does not count as a
+// use of the block, see touch().
+func (c *blockCache) peek(key cid.Cid) []byte {
+	c.mtx.Lock()
+	defer c.mtx.Unlock()
+
+	elem, ok := c.entries[key]
+	if !ok {
+		return nil
+	}
+	data, err := os.ReadFile(c.path(key))
+	if err != nil {
+		debug("CANNOT READ CACHED BLOCK", key, err)
+		c.remove(elem)
+		return nil
+	}
+	return data
+}
+
+// touch marks the blocks as the most recently used ones. Blocks that are not cached are ignored.
+func (c *blockCache) touch(keys []cid.Cid) {
+	c.mtx.Lock()
+	defer c.mtx.Unlock()
+
+	now := time.Now()
+	for _, key := range keys {
+		if elem, ok := c.entries[key]; ok {
+			c.lru.MoveToFront(elem)
+			_ = os.Chtimes(c.path(key), now, now)
+		}
+	}
+}
+
+func (c *blockCache) has(key cid.Cid) bool {
+	c.mtx.Lock()
+	defer c.mtx.Unlock()
+	_, ok := c.entries[key]
+	return ok
+}
+
+// put stores the block. The caller is responsible for verifying the data.
+func (c *blockCache) put(key cid.Cid, data []byte) {
+	if uint64(len(data)) > c.maxSize {
+		return
+	}
+
+	c.mtx.Lock()
+	defer c.mtx.Unlock()
+
+	if elem, ok := c.entries[key]; ok {
+		c.lru.MoveToFront(elem)
+		return
+	}
+
+	// Write to a temporary file first, so that readers never see partially written blocks
+	tmp, err := os.CreateTemp(c.dir, ".tmp-*")
+	if err != nil {
+		debug("CANNOT CACHE BLOCK", key, err)
+		return
+	}
+	_, err = tmp.Write(data)
+	if closeErr := tmp.Close(); err == nil {
+		err = closeErr
+	}
+	if err == nil {
+		err = os.Rename(tmp.Name(), c.path(key))
+	}
+	if err != nil {
+		_ = os.Remove(tmp.Name())
+		debug("CANNOT CACHE BLOCK", key, err)
+		return
+	}
+
+	c.entries[key] = c.lru.PushFront(&cacheEntry{key, uint64(len(data))})
+	c.size += uint64(len(data))
+	c.evict()
+}
+
+// evict removes the least recently used blocks until the cache fits into the size limit. The
+// caller must hold the lock.
+func (c *blockCache) evict() {
+	for c.size > c.maxSize {
+		back := c.lru.Back()
+		if back == nil {
+			return
+		}
+		c.remove(back)
+	}
+}
+
+func (c *blockCache) remove(elem *list.Element) {
+	entry := c.lru.Remove(elem).(*cacheEntry)
+	delete(c.entries, entry.cid)
+	c.size -= entry.size
+	if err := os.Remove(c.path(entry.cid)); err != nil && !errors.Is(err, os.ErrNotExist) {
+		debug("CANNOT REMOVE CACHED BLOCK", entry.cid, err)
+	}
+}
+
+// cachingFetcher serves retrievals from the block cache when the cache has all blocks required by
+// the request, and stores blocks retrieved from providers in the cache otherwise.
+type cachingFetcher struct {
+	inner types.Fetcher
+	cache *blockCache
+	// metrics is nil when metrics are disabled
+	metrics *daemonMetrics
+}
+
+var _ types.Fetcher = (*cachingFetcher)(nil)
+
+func (f *cachingFetcher) Fetch(ctx context.Context, request types.RetrievalRequest, opts ...types.FetchOption) (*types.RetrievalStats, error) {
+	start := time.Now()
+	if blocks, ok := f.loadAllBlocks(ctx, request); ok {
+		stats, err := f.fetchFromCache(ctx, request, blocks)
+		if err != nil {
+			// We cannot fall back to providers, because some blocks have been already written to
+			// the response.
+			return nil, fmt.Errorf("cannot write cached blocks: %w", err)
+		}
+		stats.Duration = time.Since(start)
+		debug("SERVED FROM CACHE", request.Root, "blocks:", stats.Blocks)
+		if f.metrics != nil {
+			f.metrics.cacheHits.Inc()
+		}
+		return stats, nil
+	}
+
+	request.LinkSystem.StorageWriteOpener = f.writeThrough(request.LinkSystem.StorageWriteOpener)
+	return f.inner.Fetch(ctx, request, opts...)
+}
+
+// loadAllBlocks walks the DAG selected by the request using cached blocks only, without writing
+// anything to the response. It returns the data of all visited blocks when the cache has every
+// block, so that concurrent evictions cannot break the response written afterwards.
+func (f *cachingFetcher) loadAllBlocks(ctx context.Context, request types.RetrievalRequest) (map[cid.Cid][]byte, bool) {
+	if !f.cache.has(request.Root) {
+		return nil, false
+	}
+	blocks := map[cid.Cid][]byte{}
+	lsys := cidlink.DefaultLinkSystem()
+	lsys.StorageReadOpener = func(_ linking.LinkContext, lnk datamodel.Link) (io.Reader, error) {
+		key := lnk.(cidlink.Link).Cid
+		data, ok := blocks[key]
+		if !ok {
+			data = f.cache.peek(key)
+			if data == nil {
+				return nil, errBlockNotCached
+			}
+			blocks[key] = data
+		}
+		return bytes.NewReader(data), nil
+	}
+	if _, err := f.traversal(request).Traverse(ctx, lsys, nil); err != nil {
+		return nil, false
+	}
+	return blocks, true
+}
+
+// fetchFromCache walks the DAG again and writes the blocks loaded by loadAllBlocks to the
+// request's storage.
+func (f *cachingFetcher) fetchFromCache(ctx context.Context, request types.RetrievalRequest, blocks map[cid.Cid][]byte) (*types.RetrievalStats, error) {
+	output := request.LinkSystem
+	var written []cid.Cid
+	seen := map[cid.Cid]bool{}
+	stats := &types.RetrievalStats{RootCid: request.Root}
+
+	lsys := cidlink.DefaultLinkSystem()
+	lsys.StorageReadOpener = func(lctx linking.LinkContext, lnk datamodel.Link) (io.Reader, error) {
+		key := lnk.(cidlink.Link).Cid
+		data, ok := blocks[key]
+		if !ok {
+			// The traversal is deterministic, it visits the same blocks as loadAllBlocks
+			return nil, errBlockNotCached
+		}
+		if !seen[key] {
+			seen[key] = true
+			w, commit, err := output.StorageWriteOpener(lctx)
+			if err != nil {
+				return nil, err
+			}
+			if _, err := w.Write(data); err != nil {
+				return nil, err
+			}
+			if err := commit(lnk); err != nil {
+				return nil, err
+			}
+			written = append(written, key)
+			stats.Blocks++
+			stats.Size += uint64(len(data))
+		}
+		return bytes.NewReader(data), nil
+	}
+
+	if _, err := f.traversal(request).Traverse(ctx, lsys, nil); err != nil {
+		return nil, err
+	}
+	f.cache.touch(written)
+	return stats, nil
+}
+
+func (f *cachingFetcher) traversal(request types.RetrievalRequest) traversal.Config {
+	return traversal.Config{
+		Root:      request.Root,
+		Selector:  request.GetSelector(),
+		MaxBlocks: request.MaxBlocks,
+	}
+}
+
+// writeThrough wraps the request's block writer to store every block received from providers in
+// the cache too. Lassie verifies the blocks before writing them.
+func (f *cachingFetcher) writeThrough(open linking.BlockWriteOpener) linking.BlockWriteOpener {
+	return func(lctx linking.LinkContext) (io.Writer, linking.BlockWriteCommitter, error) {
+		w, commit, err := open(lctx)
+		if err != nil {
+			return nil, nil, err
+		}
+		var buf bytes.Buffer
+		return io.MultiWriter(w, &buf), func(lnk datamodel.Link) error {
+			if err := commit(lnk); err != nil {
+				return err
+			}
+			f.cache.put(lnk.(cidlink.Link).Cid, buf.Bytes())
+			return nil
+		}, nil
+	}
+}
+
+var errBlockNotCached = errors.New("block is not cached")
@@ -8,54 +8,225 @@ package main
 //  5 trace
 
 /*
+#include <stdbool.h>
 #include <stdlib.h>
 #include <stdint.h>
 
+typedef struct {
+	const char* addr;
+	const char* protocol;
+} provider_t;
+
+// Limits of zero are not enforced.
+typedef struct {
+	const char* token;
+	uint32_t max_concurrent_retrievals;
+	uint32_t requests_per_minute;
+	uint64_t bytes_per_day;
+	uint64_t max_blocks;
+} access_token_t;
+
+typedef struct {
+	bool found;
+	uint32_t active_retrievals;
+	uint32_t requests_last_minute;
+	uint64_t bytes_today;
+} access_token_usage_t;
+
 typedef struct {
 	const char* temp_dir;
+	const char* listen_addr;
 	uint16_t port;
+	const char* unix_socket;
+	uint32_t unix_socket_mode;
 	size_t log_level;
 	uint64_t max_blocks;
 	int64_t provider_timeout;
 	int64_t global_timeout;
-	const char* access_token;
+	const access_token_t* access_tokens;
+	size_t access_tokens_len;
 	const char* lassie_user_agent;
+	const provider_t* providers;
+	size_t providers_len;
+	const char* indexer_url;
+	const char** protocols;
+	size_t protocols_len;
+	uint32_t libp2p_low_water;
+	uint32_t libp2p_high_water;
+	uint32_t concurrent_sp_retrievals;
+	uint32_t bitswap_concurrency;
+	const char** excluded_providers;
+	size_t excluded_providers_len;
+	bool metrics_enabled;
+	uint16_t metrics_port;
+	const char* cache_dir;
+	uint64_t cache_max_size;
+	bool tls_enabled;
+	const uint8_t* tls_cert;
+	size_t tls_cert_len;
+	const uint8_t* tls_key;
+	size_t tls_key_len;
 } daemon_config_t;
 
 typedef struct {
+	uint64_t handle;
 	uint16_t port;
+	uint16_t metrics_port;
 	const char* error;
+	int error_kind;
 } daemon_init_result_t;
 
 typedef struct {
 	const char * error;
+	int error_kind;
 } result_t;
+
+typedef struct {
+	const char* root;
+	const char* path;
+	const char* dag_scope;
+	bool has_entity_bytes;
+	int64_t entity_bytes_from;
+	bool has_entity_bytes_to;
+	int64_t entity_bytes_to;
+	bool duplicates;
+	uint64_t max_blocks;
+	const provider_t* providers;
+	size_t providers_len;
+	const char** protocols;
+	size_t protocols_len;
+	// fetch_id is the value returned by CreateFetch, or 0 when the retrieval cannot be cancelled
+	uint64_t fetch_id;
+} fetch_request_t;
+
+// The callback receiving CAR bytes produced by Fetch. It returns 0 on success and any other value
+// to abort the retrieval.
+typedef int (*fetch_write_fn)(void* ctx, const uint8_t* data, size_t len);
+
+static inline int call_fetch_write(fetch_write_fn write, void* ctx, const uint8_t* data, size_t len) {
+	return write(ctx, data, len);
+}
+
+// The callback receiving log messages. The level uses the numbering described at the top of this
+// file.
+typedef void (*log_fn)(size_t level, const char* target, const char* message);
+
+static inline void call_log(log_fn log, size_t level, const char* target, const char* message) {
+	log(level, target, message);
+}
+
+typedef struct {
+	const char* kind;
+	const char* retrieval_id;
+	int64_t time;
+	const char* root_cid;
+	const char* provider_id;
+	const char* protocol;
+	const char* error;
+	uint64_t count;
+	uint64_t received_bytes;
+	uint64_t received_blocks;
+	int64_t duration;
+} retrieval_event_t;
+
+// The callback receiving retrieval events of the daemon identified by the handle.
+typedef void (*event_fn)(uint64_t handle, const retrieval_event_t* event);
+
+static inline void call_event(event_fn callback, uint64_t handle, const retrieval_event_t* event) {
+	callback(handle, event);
+}
 */
 import "C"
 
 import (
 	"context"
+	"errors"
 	"fmt"
+	"io"
 	"net"
+	"net/url"
 	"os"
 	"strconv"
+	"strings"
 	"sync"
+	"sync/atomic"
 	"time"
 	"unsafe"
 
 	lassieBuild "github.com/filecoin-project/lassie/pkg/build"
+	"github.com/filecoin-project/lassie/pkg/events"
+	"github.com/filecoin-project/lassie/pkg/indexerlookup"
 	"github.com/filecoin-project/lassie/pkg/lassie"
-	httpserver "github.com/filecoin-project/lassie/pkg/server/http"
+	"github.com/filecoin-project/lassie/pkg/retriever"
+	"github.com/filecoin-project/lassie/pkg/storage"
+	"github.com/filecoin-project/lassie/pkg/types"
+	"github.com/ipfs/go-cid"
+	logging "github.com/ipfs/go-log/v2"
+	"github.com/ipld/go-car/v2"
+	trustlessutils "github.com/ipld/go-trustless-utils"
+	"github.com/ipni/go-libipni/metadata"
+	"github.com/libp2p/go-libp2p"
+	"github.com/libp2p/go-libp2p/core/host"
+	"github.com/libp2p/go-libp2p/core/peer"
+	"github.com/libp2p/go-libp2p/p2p/net/connmgr"
+	"github.com/multiformats/go-multicodec"
+	"go.uber.org/zap/zapcore"
 )
 
+// daemonInstance holds the state of one daemon created by InitDaemon.
+type daemonInstance struct {
+	server       *daemonServer
+	accessTokens *accessTokens
+	host         host.Host
+	// fetcher is either the Lassie instance or the cachingFetcher wrapping it
+	fetcher   types.Fetcher
+	tempDir   string
+	maxBlocks uint64
+	metrics   *daemonMetrics
+	// unsubscribe removes the subscriber forwarding retrieval events to Rust
+	unsubscribe func()
+}
+
+// mtx protects `daemons`, `nextHandle` and `debug_log_enabled`.
 var mtx sync.Mutex
-var daemon *httpserver.HttpServer
+var daemons = map[uint64]*daemonInstance{}
+var nextHandle uint64 = 1
 var debug_log_enabled bool
 
+// logCallback holds the C.log_fn registered by SetLogCallback.
+var logCallback atomic.Value
+
+// eventCallback holds the C.event_fn registered by SetEventCallback.
+var eventCallback atomic.Value
+
+// fetchMtx protects `fetches` and `nextFetchId`.
+var fetchMtx sync.Mutex
+
+// fetches holds the contexts created by CreateFetch until the retrieval finishes or is cancelled.
+var fetches = map[uint64]fetchContext{}
+var nextFetchId uint64 = 1
+
+type fetchContext struct {
+	ctx    context.Context
+	cancel context.CancelFunc
+}
+
 var OK C.result_t = C.result_t{error: nil}
 
-// InitDaemon initializes Lassie HTTP daemon listening on localhost and returns the port number.
-// The daemon is a singleton - there can be only one instance running in the host process.
+// Values of result_t.error_kind, they must be kept in sync with the definitions in
+// src/fetch_error.rs (retrieval errors) and src/tls_error.rs (TLS errors)
+const (
+	errorKindOther          = 0
+	errorKindNotFound       = 1
+	errorKindTimeout        = 2
+	errorKindTlsCertificate = 3
+	errorKindTlsKey         = 4
+	errorKindTlsKeyMismatch = 5
+)
+
+// InitDaemon initializes Lassie HTTP daemon listening on the configured address and returns the handle
+// identifying the daemon in other calls, and the port number. The host process can run any number
+// of daemons.
 //
 // **Important:** This function does not run the request handler, you must call RunDaemon().
 //
@@ -74,25 +245,40 @@ func InitDaemon(cfg *C.daemon_config_t) C.daemon_init_result_t {
 	defer debug("InitDaemon lock released")
 
 	debug_log_enabled = wants_debug_log
-
-	if daemon != nil {
-		return newInitError("cannot create more than one Lassie daemon", nil)
-	}
+	setGoLogLevel(cfg.log_level)
 
 	var tempDir string = C.GoString(cfg.temp_dir)
-	accessToken := C.GoString(cfg.access_token)
+	listenAddr := C.GoString(cfg.listen_addr)
+	tokens := parseAccessTokens(cfg.access_tokens, cfg.access_tokens_len)
 	lassieBuild.UserAgent = C.GoString(cfg.lassie_user_agent)
 
+	providers, err := parseProviders(cfg.providers, cfg.providers_len)
+	if err != nil {
+		return newInitError("invalid providers", err)
+	}
+
+	indexerUrl := C.GoString(cfg.indexer_url)
+
+	protocols, err := parseProtocols(cfg.protocols, cfg.protocols_len)
+	if err != nil {
+		return newInitError("invalid protocols", err)
+	}
+
+	excludedProviders, err := parsePeerIds(cfg.excluded_providers, cfg.excluded_providers_len)
+	if err != nil {
+		return newInitError("invalid excluded providers", err)
+	}
+
 	if debug_log_enabled {
 		tempDirStr := fmt.Sprintf("`%s`", tempDir)
 		if tempDir == "" {
 			tempDirStr = "<empty>"
 		}
-		accessTokenStr := fmt.Sprintf("`%s`", accessToken)
-		if accessToken == "" {
+		accessTokenStr := fmt.Sprintf("%d tokens", len(tokens))
+		if len(tokens) == 0 {
 			accessTokenStr = "<not configured>"
 		}
-		debug(fmt.Sprintf("Lassie configuration:\n  log_level=%d\n  port=%d\n  temp_dir=%v\n  accessToken=%v", cfg.log_level, cfg.port, tempDirStr, accessTokenStr))
+		debug(fmt.Sprintf("Lassie configuration:\n  log_level=%d\n  listen_addr=%s\n  port=%d\n  unix_socket=%q\n  temp_dir=%v\n  accessTokens=%v\n  providers=%v\n  indexer_url=%v\n  protocols=%v\n  excluded_providers=%v", cfg.log_level, listenAddr, cfg.port, C.GoString(cfg.unix_socket), tempDirStr, accessTokenStr, providers, indexerUrl, protocols, excludedProviders))
 	}
 
 	lassieOpts := []lassie.LassieOption{
@@ -100,20 +286,76 @@ func InitDaemon(cfg *C.daemon_config_t) C.daemon_init_result_t {
 		lassie.WithGlobalTimeout(time.Duration(cfg.global_timeout)),
 	}
 
-	// TODO: configure Libp2p connection manager (LowWater, HighWater)
-	// TODO: configure max concurrent SP retrievals
-	// connManager, err := connmgr.NewConnManager(libp2pLowWater, libp2pHighWater)
-	// if err != nil {
-	// 	return err
-	// }
-	// lassieOpts = append(
-	// 	lassieOpts,
-	// 	lassie.WithLibp2pOpts(libp2p.ConnectionManager(connManager)),
-	// 	lassie.WithConcurrentSPRetrievals(concurrentSPRetrievals),
-	// )
+	if len(protocols) > 0 {
+		lassieOpts = append(lassieOpts, lassie.WithProtocols(protocols))
+	}
+
+	if len(excludedProviders) > 0 {
+		lassieOpts = append(lassieOpts, lassie.WithProviderBlockList(excludedProviders))
+	}
+
+	var libp2pOpts []libp2p.Option
+	if cfg.libp2p_high_water > 0 {
+		connManager, err := connmgr.NewConnManager(int(cfg.libp2p_low_water), int(cfg.libp2p_high_water))
+		if err != nil {
+			return newInitError("cannot create libp2p connection manager", err)
+		}
+		libp2pOpts = append(libp2pOpts, libp2p.ConnectionManager(connManager))
+	}
+
+	if cfg.concurrent_sp_retrievals > 0 {
+		lassieOpts = append(lassieOpts, lassie.WithConcurrentSPRetrievals(uint(cfg.concurrent_sp_retrievals)))
+	}
+
+	if cfg.bitswap_concurrency > 0 {
+		lassieOpts = append(lassieOpts, lassie.WithBitswapConcurrency(int(cfg.bitswap_concurrency)))
+	}
+
+	if len(providers) == 0 && indexerUrl != "" {
+		endpoint, err := url.ParseRequestURI(indexerUrl)
+		if err != nil {
+			return newInitError(fmt.Sprintf("invalid indexer URL `%s`", indexerUrl), err)
+		}
+		candidateSource, err := indexerlookup.NewCandidateSource(indexerlookup.WithHttpEndpoint(endpoint))
+		if err != nil {
+			return newInitError("cannot create indexer candidate source", err)
+		}
+		lassieOpts = append(lassieOpts, lassie.WithCandidateSource(candidateSource))
+	}
 
-	// TODO: configure bitswap concurrency
-	// lassieOpts = append(lassieOpts, lassie.WithBitswapConcurrency(bitswapConcurrency))
+	// We create the libp2p host ourselves even when Lassie could create one, because Lassie does
+	// not close its host. Owning the host lets us release it when the daemon is stopped or when
+	// the initialization fails.
+	lassieHost, err := libp2p.New(libp2pOpts...)
+	if err != nil {
+		return newInitError("cannot create libp2p host", err)
+	}
+	lassieOpts = append(lassieOpts, lassie.WithHost(lassieHost))
+	if len(providers) > 0 {
+		// The host discovers protocols supported by providers that were configured without a
+		// protocol hint.
+		lassieOpts = append(
+			lassieOpts,
+			lassie.WithCandidateSource(retriever.NewDirectCandidateSource(providers, retriever.WithLibp2pCandidateDiscovery(lassieHost))),
+		)
+	}
+
+	// Release everything created below when returning an error
+	var server *daemonServer
+	var metrics *daemonMetrics
+	initialized := false
+	defer func() {
+		if initialized {
+			return
+		}
+		if metrics != nil {
+			metrics.close()
+		}
+		if server != nil {
+			server.close()
+		}
+		closeHost(lassieHost)
+	}()
 
 	ctx := context.Background()
 
@@ -122,26 +364,202 @@ func InitDaemon(cfg *C.daemon_config_t) C.daemon_init_result_t {
 		return newInitError("cannot create Lassie instance", err)
 	}
 
-	daemon, err = httpserver.NewHttpServer(ctx, lassie, httpserver.HttpServerConfig{
-		Address:             "127.0.0.1",
-		Port:                uint(cfg.port),
-		TempDir:             tempDir,
-		MaxBlocksPerRequest: uint64(cfg.max_blocks),
-		AccessToken:         accessToken,
-	})
+	// The metrics server listens on the same address as the HTTP server, but on its own port
+	var metricsPort uint16
+	if cfg.metrics_enabled {
+		metrics, err = newDaemonMetrics(net.JoinHostPort(listenAddr, strconv.Itoa(int(cfg.metrics_port))))
+		if err == nil {
+			metricsPort, err = metrics.port()
+		}
+		if err != nil {
+			return newInitError("cannot start the metrics server", err)
+		}
+	}
+
+	var fetcher types.Fetcher = lassie
+	if cacheDir := C.GoString(cfg.cache_dir); cacheDir != "" {
+		cache, err := openBlockCache(cacheDir, uint64(cfg.cache_max_size))
+		if err != nil {
+			return newInitError(fmt.Sprintf("cannot open block cache `%s`", cacheDir), err)
+		}
+		fetcher = &cachingFetcher{inner: lassie, cache: cache, metrics: metrics}
+	}
 
+	accessTokens := newAccessTokens(fetcher, tempDir, uint64(cfg.max_blocks), tokens)
+	handler := newDaemonHandler(accessTokens, metrics)
+	if unixSocket := C.GoString(cfg.unix_socket); unixSocket != "" {
+		server, err = newUnixServer(unixSocket, os.FileMode(cfg.unix_socket_mode)&os.ModePerm, handler)
+	} else {
+		server, err = newTCPServer(listenAddr, uint16(cfg.port), handler)
+	}
 	if err != nil {
 		return newInitError("cannot start the HTTP server", err)
 	}
+	if cfg.tls_enabled {
+		cert, err := loadCertificate(cBytes(cfg.tls_cert, cfg.tls_cert_len), cBytes(cfg.tls_key, cfg.tls_key_len))
+		if err != nil {
+			return newInitError("cannot load TLS certificate", err)
+		}
+		server.enableTLS(cert)
+	}
+	port := server.port()
 
-	port, err := getPort()
-	if err != nil {
-		return newInitError("cannot parse HTTP server port", err)
+	handle := nextHandle
+	nextHandle++
+	unsubscribe := lassie.RegisterSubscriber(func(event types.RetrievalEvent) {
+		if metrics != nil {
+			metrics.observe(event)
+		}
+		forwardEvent(handle, event)
+	})
+	daemons[handle] = &daemonInstance{
+		server:       server,
+		accessTokens: accessTokens,
+		host:         lassieHost,
+		fetcher:      fetcher,
+		tempDir:      tempDir,
+		maxBlocks:    uint64(cfg.max_blocks),
+		metrics:      metrics,
+		unsubscribe:  unsubscribe,
 	}
+	initialized = true
+	debug("CREATED LASSIE DAEMON", handle)
 
 	return C.daemon_init_result_t{
-		port:  C.ushort(port),
-		error: nil,
+		handle:       C.uint64_t(handle),
+		port:         C.ushort(port),
+		metrics_port: C.ushort(metricsPort),
+		error:        nil,
+	}
+}
+
+// cBytes copies the C byte array to a Go slice.
+func cBytes(data *C.uint8_t, size C.size_t) []byte {
+	if size == 0 {
+		return nil
+	}
+	return C.GoBytes(unsafe.Pointer(data), C.int(size))
+}
+
+func parseProviders(list *C.provider_t, count C.size_t) ([]types.Provider, error) {
+	if count == 0 {
+		return nil, nil
+	}
+
+	items := unsafe.Slice(list, int(count))
+	providers := make([]types.Provider, 0, len(items))
+	for _, item := range items {
+		addr := C.GoString(item.addr)
+		addrInfo, err := peer.AddrInfoFromString(addr)
+		if err != nil {
+			return nil, fmt.Errorf("cannot parse provider address `%s`: %w", addr, err)
+		}
+
+		provider := types.Provider{Peer: *addrInfo}
+		if name := C.GoString(item.protocol); name != "" {
+			protocol, err := parseProtocol(name)
+			if err != nil {
+				return nil, err
+			}
+			provider.Protocols = []metadata.Protocol{protocol}
+		}
+
+		providers = append(providers, provider)
+	}
+
+	return providers, nil
+}
+
+func parseAccessTokens(list *C.access_token_t, count C.size_t) []accessToken {
+	if count == 0 {
+		return nil
+	}
+
+	items := unsafe.Slice(list, int(count))
+	tokens := make([]accessToken, 0, len(items))
+	for _, item := range items {
+		tokens = append(tokens, accessToken{
+			value:             C.GoString(item.token),
+			maxConcurrent:     uint32(item.max_concurrent_retrievals),
+			requestsPerMinute: uint32(item.requests_per_minute),
+			bytesPerDay:       uint64(item.bytes_per_day),
+			maxBlocks:         uint64(item.max_blocks),
+		})
+	}
+	return tokens
+}
+
+func parsePeerIds(list **C.char, count C.size_t) (map[peer.ID]bool, error) {
+	if count == 0 {
+		return nil, nil
+	}
+
+	values := unsafe.Slice(list, int(count))
+	ids := make(map[peer.ID]bool, len(values))
+	for _, value := range values {
+		str := C.GoString(value)
+		id, err := peer.Decode(str)
+		if err != nil {
+			return nil, fmt.Errorf("cannot parse peer ID `%s`: %w", str, err)
+		}
+		ids[id] = true
+	}
+
+	return ids, nil
+}
+
+func parseProtocols(list **C.char, count C.size_t) ([]multicodec.Code, error) {
+	if count == 0 {
+		return nil, nil
+	}
+
+	names := unsafe.Slice(list, int(count))
+	codes := make([]multicodec.Code, 0, len(names))
+	for _, name := range names {
+		protocol, err := parseProtocol(C.GoString(name))
+		if err != nil {
+			return nil, err
+		}
+		codes = append(codes, protocol.ID())
+	}
+
+	return codes, nil
+}
+
+// The protocol names must be kept in sync with Protocol::as_c_str() in src/protocol.rs
+func parseProtocol(name string) (metadata.Protocol, error) {
+	switch name {
+	case "bitswap":
+		return metadata.Bitswap{}, nil
+	case "graphsync":
+		return &metadata.GraphsyncFilecoinV1{}, nil
+	case "http":
+		return &metadata.IpfsGatewayHttp{}, nil
+	default:
+		return nil, fmt.Errorf("unknown protocol `%s`", name)
+	}
+}
+
+// The protocol names must be kept in sync with Protocol::from_name() in src/protocol.rs
+func protocolName(code multicodec.Code) string {
+	switch code {
+	case multicodec.TransportBitswap:
+		return "bitswap"
+	case multicodec.TransportGraphsyncFilecoinv1:
+		return "graphsync"
+	case multicodec.TransportIpfsGatewayHttp:
+		return "http"
+	default:
+		return code.String()
+	}
+}
+
+func closeHost(h host.Host) {
+	if h == nil {
+		return
+	}
+	if err := h.Close(); err != nil {
+		debug("CANNOT CLOSE LIBP2P HOST:", err)
 	}
 }
 
@@ -151,8 +569,9 @@ func newInitError(msg string, cause error) C.daemon_init_result_t {
 	}
 
 	return C.daemon_init_result_t{
-		port:  0,
-		error: C.CString(msg),
+		port:       0,
+		error:      C.CString(msg),
+		error_kind: tlsErrorKind(cause),
 	}
 }
 
@@ -176,6 +595,29 @@ func newError(msg string, cause error) C.result_t {
 	}
 }
 
+// newFetchError creates an error result reporting the kind of the retrieval failure, so that the
+// Rust side can tell apart content that's not available from retrievals that took too long.
+func newFetchError(msg string, cause error) C.result_t {
+	result := newError(msg, cause)
+	switch {
+	case errors.Is(cause, retriever.ErrNoCandidates):
+		result.error_kind = errorKindNotFound
+	case errors.Is(cause, retriever.ErrRetrievalTimedOut), errors.Is(cause, context.DeadlineExceeded):
+		result.error_kind = errorKindTimeout
+	}
+	return result
+}
+
+// tlsErrorKind returns the kind of the TLS configuration problem reported by the error, or
+// errorKindOther when the error is not related to TLS.
+func tlsErrorKind(err error) C.int {
+	var tlsErr *tlsError
+	if errors.As(err, &tlsErr) {
+		return C.int(tlsErr.kind)
+	}
+	return errorKindOther
+}
+
 // DropResult cleans up any resources allocated for and owned by the result_t value.
 //
 //export DropResult
@@ -192,16 +634,16 @@ func DropResult(result *C.result_t) {
 // **Important:** This function does not exit until you call StopDaemon from a different thread.
 //
 //export RunDaemon
-func RunDaemon() C.result_t {
-	server := getDaemon()
+func RunDaemon(handle C.uint64_t) C.result_t {
+	instance := getDaemon(uint64(handle))
 
-	if server == nil {
-		// The server may have been cleaned by now if StopDaemon was called quickly after StartDaemon
+	if instance == nil {
+		// The daemon may have been cleaned by now if StopDaemon was called quickly after InitDaemon
 		return OK
 	}
 
-	debug("RUNNING LASSIE HANDLER")
-	err := server.Start()
+	debug("RUNNING LASSIE HANDLER", handle)
+	err := instance.server.start()
 	debug("LASSIE HANDLER EXITED:", err)
 	if err != nil {
 		return newError("Lassie HTTP server error", err)
@@ -210,50 +652,480 @@ func RunDaemon() C.result_t {
 	return OK
 }
 
-func getDaemon() *httpserver.HttpServer {
-	debug("RunDaemon locking the mutex")
+func getDaemon(handle uint64) *daemonInstance {
+	debug("getDaemon locking the mutex")
 	mtx.Lock()
 	defer mtx.Unlock()
-	defer debug("RunDaemon lock released")
+	defer debug("getDaemon lock released")
 
-	return daemon
+	return daemons[handle]
 }
 
-// CloseDaemon stops the Lassie HTTP daemon.
+// StopDaemon stops the Lassie HTTP daemon identified by the handle.
 //
 //export StopDaemon
-func StopDaemon() C.result_t {
+func StopDaemon(handle C.uint64_t) C.result_t {
 	debug("StopDaemon locking the mutex")
 	mtx.Lock()
 	defer mtx.Unlock()
 	defer debug("StopDaemon lock released")
 
-	if daemon == nil {
+	instance := daemons[uint64(handle)]
+	if instance == nil {
 		return newError("Lassie daemon not running, cannot stop it", nil)
 	}
 
-	debug("STOPPING LASSIE HANDLER")
-	err := daemon.Close()
+	debug("STOPPING LASSIE HANDLER", handle)
+	err := instance.server.close()
 	debug("STOP ERROR?", err)
+
+	// Release the instance even when the server did not close cleanly, the caller does not stop
+	// the same daemon twice
+	instance.unsubscribe()
+	if instance.metrics != nil {
+		instance.metrics.close()
+	}
+	closeHost(instance.host)
+	delete(daemons, uint64(handle))
+
 	if err != nil {
 		return newError("Cannot stop Lassie HTTP server", err)
 	}
+	return OK
+}
+
+// SetAccessTokens replaces the access tokens accepted by the HTTP server of the daemon identified by
+// the handle. Requests that are already running are not affected. An empty list disables the auth
+// check. The usage counters of tokens that remain configured are preserved.
+//
+//export SetAccessTokens
+func SetAccessTokens(handle C.uint64_t, tokens *C.access_token_t, tokensLen C.size_t) C.result_t {
+	instance := getDaemon(uint64(handle))
+	if instance == nil {
+		return newError("Lassie daemon not running, cannot set access tokens", nil)
+	}
+
+	instance.accessTokens.set(parseAccessTokens(tokens, tokensLen))
+	debug("UPDATED ACCESS TOKENS", handle, "count:", tokensLen)
+	return OK
+}
+
+// GetAccessTokenUsage reports the resources consumed by the requests authorized by the token. The
+// `found` field of the usage is false when the daemon does not accept the token.
+//
+//export GetAccessTokenUsage
+func GetAccessTokenUsage(handle C.uint64_t, token *C.char, usage *C.access_token_usage_t) C.result_t {
+	instance := getDaemon(uint64(handle))
+	if instance == nil {
+		return newError("Lassie daemon not running, cannot get access token usage", nil)
+	}
+
+	snapshot, found := instance.accessTokens.getUsage(C.GoString(token))
+	*usage = C.access_token_usage_t{
+		found:                C.bool(found),
+		active_retrievals:    C.uint32_t(snapshot.active),
+		requests_last_minute: C.uint32_t(snapshot.requestsLastMinute),
+		bytes_today:          C.uint64_t(snapshot.bytesToday),
+	}
+	return OK
+}
+
+// ReloadTls replaces the TLS certificate and private key of the daemon identified by the handle.
+// New connections use the new certificate, established connections are not affected.
+//
+//export ReloadTls
+func ReloadTls(handle C.uint64_t, cert *C.uint8_t, certLen C.size_t, key *C.uint8_t, keyLen C.size_t) C.result_t {
+	instance := getDaemon(uint64(handle))
+	if instance == nil {
+		return newError("Lassie daemon not running, cannot reload TLS certificate", nil)
+	}
+
+	certificate, err := loadCertificate(cBytes(cert, certLen), cBytes(key, keyLen))
+	if err != nil {
+		result := newError("cannot load TLS certificate", err)
+		result.error_kind = tlsErrorKind(err)
+		return result
+	}
+	if err := instance.server.setCertificate(certificate); err != nil {
+		return newError("cannot reload TLS certificate", err)
+	}
 
-	daemon = nil
+	debug("RELOADED TLS CERTIFICATE", handle)
 	return OK
 }
 
-func getPort() (uint16, error) {
-	_, portStr, err := net.SplitHostPort(daemon.Addr())
+// Fetch retrieves the content described by the request using the Lassie instance of the daemon
+// identified by the handle, without going through the HTTP server. The CAR bytes are passed to the
+// `write` callback as they arrive. This function does not return until the retrieval is finished
+// or cancelled via CancelFetch.
+//
+//export Fetch
+func Fetch(handle C.uint64_t, request *C.fetch_request_t, write C.fetch_write_fn, writeCtx unsafe.Pointer) C.result_t {
+	instance := getDaemon(uint64(handle))
+	if instance == nil {
+		return newError("Lassie daemon not running, cannot fetch", nil)
+	}
+	ctx := context.Background()
+	if request.fetch_id != 0 {
+		fetch, ok := lookupFetch(uint64(request.fetch_id))
+		if !ok {
+			return newError("the retrieval was cancelled", context.Canceled)
+		}
+		ctx = fetch.ctx
+		// Release the context once the retrieval is finished
+		defer CancelFetch(request.fetch_id)
+	}
+
+	fetcher := instance.fetcher
+	tempDir := instance.tempDir
+	maxBlocks := instance.maxBlocks
+	metrics := instance.metrics
+
+	rootStr := C.GoString(request.root)
+	rootCid, err := cid.Parse(rootStr)
 	if err != nil {
-		return 0, fmt.Errorf("malformed server address `%s`: %+v", daemon.Addr(), err)
+		return newError(fmt.Sprintf("invalid CID `%s`", rootStr), err)
 	}
-	port, err := strconv.ParseUint(portStr, 10, 16)
+
+	dagScope, err := parseDagScope(C.GoString(request.dag_scope))
+	if err != nil {
+		return newError("invalid DAG scope", err)
+	}
+
+	var byteRange *trustlessutils.ByteRange
+	if request.has_entity_bytes {
+		byteRange = &trustlessutils.ByteRange{From: int64(request.entity_bytes_from)}
+		if request.has_entity_bytes_to {
+			to := int64(request.entity_bytes_to)
+			byteRange.To = &to
+		}
+	}
+
+	providers, err := parseProviders(request.providers, request.providers_len)
+	if err != nil {
+		return newError("invalid providers", err)
+	}
+
+	protocols, err := parseProtocols(request.protocols, request.protocols_len)
+	if err != nil {
+		return newError("invalid protocols", err)
+	}
+
+	output := &callbackWriter{write: write, ctx: writeCtx, metrics: metrics}
+	path := C.GoString(request.path)
+
+	// Mirror the setup used by Lassie's HTTP handler
+	tempStore := storage.NewDeferredStorageCar(tempDir, rootCid)
+	var carWriter storage.DeferredWriter
+	if request.duplicates {
+		carWriter = storage.NewDuplicateAdderCarForStream(ctx, output, rootCid, path, dagScope, byteRange, tempStore)
+	} else {
+		carWriter = storage.NewDeferredCarWriterForStream(output, []cid.Cid{rootCid}, car.WriteAsCarV1(true))
+	}
+	carStore := storage.NewCachingTempStore(carWriter.BlockWriteOpener(), tempStore)
+	defer func() {
+		if err := carWriter.Close(); err != nil {
+			debug("CANNOT CLOSE CAR WRITER:", err)
+		}
+		if err := carStore.Close(); err != nil {
+			debug("CANNOT CLOSE CAR STORE:", err)
+		}
+	}()
+
+	retrievalRequest, err := types.NewRequestForPath(carStore, rootCid, path, dagScope, byteRange)
+	if err != nil {
+		return newError("cannot create retrieval request", err)
+	}
+	retrievalRequest.Duplicates = bool(request.duplicates)
+	retrievalRequest.MaxBlocks = maxBlocks
+	if request.max_blocks > 0 {
+		retrievalRequest.MaxBlocks = uint64(request.max_blocks)
+	}
+	retrievalRequest.Providers = providers
+	retrievalRequest.Protocols = protocols
+
+	debug("FETCHING", rootCid, "path:", path, "scope:", dagScope)
+	if metrics != nil {
+		metrics.fetchCalls.Inc()
+	}
+	stats, err := fetcher.Fetch(ctx, retrievalRequest)
 	if err != nil {
-		return 0, fmt.Errorf("invalid port number `%s`: %+v", portStr, err)
+		return newFetchError("retrieval failed", err)
 	}
+	debug("FETCH FINISHED:", stats)
+
+	return OK
+}
+
+// CreateFetch creates the context of a retrieval that can be aborted by CancelFetch. Pass the
+// returned id in fetch_request_t.fetch_id.
+//
+//export CreateFetch
+func CreateFetch() C.uint64_t {
+	ctx, cancel := context.WithCancel(context.Background())
 
-	return uint16(port), nil
+	fetchMtx.Lock()
+	defer fetchMtx.Unlock()
+
+	id := nextFetchId
+	nextFetchId++
+	fetches[id] = fetchContext{ctx: ctx, cancel: cancel}
+	return C.uint64_t(id)
+}
+
+// CancelFetch aborts the retrieval created by CreateFetch and releases its context. It does nothing
+// when the retrieval has already finished.
+//
+//export CancelFetch
+func CancelFetch(id C.uint64_t) {
+	fetchMtx.Lock()
+	fetch, ok := fetches[uint64(id)]
+	delete(fetches, uint64(id))
+	fetchMtx.Unlock()
+
+	if ok {
+		fetch.cancel()
+	}
+}
+
+func lookupFetch(id uint64) (fetchContext, bool) {
+	fetchMtx.Lock()
+	defer fetchMtx.Unlock()
+
+	fetch, ok := fetches[id]
+	return fetch, ok
+}
+
+type callbackWriter struct {
+	write C.fetch_write_fn
+	ctx   unsafe.Pointer
+	// metrics is nil when metrics are disabled
+	metrics *daemonMetrics
+}
+
+var _ io.Writer = (*callbackWriter)(nil)
+
+func (w *callbackWriter) Write(data []byte) (int, error) {
+	if len(data) == 0 {
+		return 0, nil
+	}
+
+	// The callback copies the data before returning, it does not keep the Go pointer
+	rc := C.call_fetch_write(w.write, w.ctx, (*C.uint8_t)(unsafe.Pointer(&data[0])), C.size_t(len(data)))
+	if rc != 0 {
+		return 0, errors.New("the consumer closed the stream")
+	}
+
+	if w.metrics != nil {
+		w.metrics.fetchBytes.Add(float64(len(data)))
+	}
+	return len(data), nil
+}
+
+// The scope names must be kept in sync with DagScope::as_c_str() in src/fetch.rs
+func parseDagScope(name string) (trustlessutils.DagScope, error) {
+	switch name {
+	case "all":
+		return trustlessutils.DagScopeAll, nil
+	case "entity":
+		return trustlessutils.DagScopeEntity, nil
+	case "block":
+		return trustlessutils.DagScopeBlock, nil
+	default:
+		return "", fmt.Errorf("unknown DAG scope `%s`", name)
+	}
+}
+
+// SetLogCallback registers the function receiving log messages of Lassie and of this wrapper. The
+// messages are printed to stderr until a callback is registered.
+//
+//export SetLogCallback
+func SetLogCallback(callback C.log_fn) {
+	logCallback.Store(callback)
+	logging.SetPrimaryCore(&callbackCore{encoder: newLogEncoder()})
+}
+
+// setGoLogLevel configures the level of all go-log subsystems from our log level.
+func setGoLogLevel(level C.size_t) {
+	switch {
+	case level == 0:
+		logging.SetAllLoggers(logging.LevelFatal)
+	case level == 1:
+		logging.SetAllLoggers(logging.LevelError)
+	case level == 2:
+		logging.SetAllLoggers(logging.LevelWarn)
+	case level == 3:
+		logging.SetAllLoggers(logging.LevelInfo)
+	default:
+		logging.SetAllLoggers(logging.LevelDebug)
+	}
+}
+
+// callbackCore is a zapcore.Core forwarding go-log entries to the log callback. The filtering by
+// level is done by go-log, see setGoLogLevel().
+type callbackCore struct {
+	encoder zapcore.Encoder
+}
+
+func newLogEncoder() zapcore.Encoder {
+	// The console encoder formats the entry as the message followed by the fields encoded as JSON.
+	// The level, the time and the logger name are passed to the callback separately.
+	return zapcore.NewConsoleEncoder(zapcore.EncoderConfig{
+		MessageKey:       "msg",
+		ConsoleSeparator: " ",
+	})
+}
+
+func (c *callbackCore) Enabled(zapcore.Level) bool {
+	return true
+}
+
+func (c *callbackCore) With(fields []zapcore.Field) zapcore.Core {
+	clone := &callbackCore{encoder: c.encoder.Clone()}
+	for _, f := range fields {
+		f.AddTo(clone.encoder)
+	}
+	return clone
+}
+
+func (c *callbackCore) Check(entry zapcore.Entry, checked *zapcore.CheckedEntry) *zapcore.CheckedEntry {
+	return checked.AddCore(entry, c)
+}
+
+func (c *callbackCore) Write(entry zapcore.Entry, fields []zapcore.Field) error {
+	buf, err := c.encoder.EncodeEntry(entry, fields)
+	if err != nil {
+		return err
+	}
+	defer buf.Free()
+
+	var level C.size_t
+	switch {
+	case entry.Level >= zapcore.ErrorLevel:
+		level = 1
+	case entry.Level == zapcore.WarnLevel:
+		level = 2
+	case entry.Level == zapcore.InfoLevel:
+		level = 3
+	default:
+		level = 4
+	}
+
+	// go-log names subsystems like "lassie/retriever", we turn them into "lassie::go::lassie::retriever"
+	target := "lassie::go::" + strings.ReplaceAll(entry.LoggerName, "/", "::")
+	writeLog(level, target, strings.TrimSuffix(buf.String(), "\n"))
+	return nil
+}
+
+func (c *callbackCore) Sync() error {
+	return nil
+}
+
+func writeLog(level C.size_t, target string, message string) {
+	callback, _ := logCallback.Load().(C.log_fn)
+	if callback == nil {
+		fmt.Fprintf(os.Stderr, "[%s] %s\n", target, message)
+		return
+	}
+
+	cTarget := C.CString(target)
+	defer C.free(unsafe.Pointer(cTarget))
+	cMessage := C.CString(message)
+	defer C.free(unsafe.Pointer(cMessage))
+	C.call_log(callback, level, cTarget, cMessage)
+}
+
+// SetEventCallback registers the function receiving retrieval events of all daemons. Events are
+// discarded until a callback is registered.
+//
+//export SetEventCallback
+func SetEventCallback(callback C.event_fn) {
+	eventCallback.Store(callback)
+}
+
+// forwardEvent converts the Lassie event to C.retrieval_event_t and passes it to the event callback.
+// The kinds must be kept in sync with RetrievalEventKind::from_go() in src/events.rs
+func forwardEvent(handle uint64, event types.RetrievalEvent) {
+	callback, _ := eventCallback.Load().(C.event_fn)
+	if callback == nil {
+		return
+	}
+
+	var kind, rootCid, providerId, protocol, errorMessage string
+	var count, receivedBytes, receivedBlocks uint64
+	var duration time.Duration
+
+	switch e := event.(type) {
+	case events.StartedFetchEvent:
+		kind = "started"
+		rootCid = e.RootCid().String()
+	case events.CandidatesFoundEvent:
+		kind = "candidates-found"
+		count = uint64(len(e.Candidates()))
+	case events.CandidatesFilteredEvent:
+		kind = "candidates-filtered"
+		count = uint64(len(e.Candidates()))
+	case events.StartedRetrievalEvent:
+		kind = "provider-started"
+		providerId = e.ProviderId().String()
+		protocol = protocolName(e.Protocol())
+	case events.ConnectedToProviderEvent:
+		kind = "provider-connected"
+		providerId = e.ProviderId().String()
+		protocol = protocolName(e.Protocol())
+	case events.FirstByteEvent:
+		kind = "first-byte"
+		providerId = e.ProviderId().String()
+		protocol = protocolName(e.Protocol())
+		duration = e.Duration()
+	case events.FailedRetrievalEvent:
+		kind = "provider-failed"
+		providerId = e.ProviderId().String()
+		protocol = protocolName(e.Protocol())
+		errorMessage = e.ErrorMessage()
+	case events.SucceededEvent:
+		kind = "succeeded"
+		providerId = e.ProviderId().String()
+		protocol = protocolName(e.Protocol())
+		receivedBytes = e.ReceivedBytesSize()
+		receivedBlocks = e.ReceivedCidsCount()
+		duration = e.Duration()
+	case events.FailedEvent:
+		kind = "failed"
+		errorMessage = e.ErrorMessage()
+	case events.FinishedEvent:
+		kind = "finished"
+	default:
+		// Other events (e.g. received blocks) are too noisy to be forwarded
+		return
+	}
+
+	var allocated []unsafe.Pointer
+	cString := func(value string) *C.char {
+		str := C.CString(value)
+		allocated = append(allocated, unsafe.Pointer(str))
+		return str
+	}
+	defer func() {
+		for _, ptr := range allocated {
+			C.free(ptr)
+		}
+	}()
+
+	cEvent := C.retrieval_event_t{
+		kind:            cString(kind),
+		retrieval_id:    cString(event.RetrievalId().String()),
+		time:            C.int64_t(event.Time().UnixNano()),
+		root_cid:        cString(rootCid),
+		provider_id:     cString(providerId),
+		protocol:        cString(protocol),
+		error:           cString(errorMessage),
+		count:           C.uint64_t(count),
+		received_bytes:  C.uint64_t(receivedBytes),
+		received_blocks: C.uint64_t(receivedBlocks),
+		duration:        C.int64_t(duration),
+	}
+	C.call_event(callback, C.uint64_t(handle), &cEvent)
 }
 
 func debug(a ...any) {
@@ -263,8 +1135,7 @@ func debug(a ...any) {
 }
 
 func print_debug(a ...any) {
-	fmt.Fprint(os.Stderr, "[LASSIE GO WRAPPER] ")
-	fmt.Fprintln(os.Stderr, a...)
+	writeLog(4, "lassie::go::wrapper", strings.TrimSuffix(fmt.Sprintln(a...), "\n"))
 }
 
 func main() {}
@@ -0,0 +1,159 @@
+package main
+
+import (
+	"errors"
+	"fmt"
+	"net"
+	"net/http"
+	"time"
+
+	"github.com/filecoin-project/lassie/pkg/events"
+	"github.com/filecoin-project/lassie/pkg/types"
+	"github.com/prometheus/client_golang/prometheus"
+	"github.com/prometheus/client_golang/prometheus/promhttp"
+)
+
+// daemonMetrics collects Prometheus metrics from the retrieval events, the HTTP requests, the
+// in-process fetches and the block cache of one daemon and serves them at `/metrics` of a dedicated
+// HTTP server.
+type daemonMetrics struct {
+	listener net.Listener
+	server   *http.Server
+
+	retrievals         prometheus.Counter
+	activeRetrievals   prometheus.Gauge
+	failedRetrievals   prometheus.Counter
+	providerRetrievals *prometheus.CounterVec
+	receivedBytes      *prometheus.CounterVec
+	timeToFirstByte    *prometheus.HistogramVec
+	httpRequests       *prometheus.CounterVec
+	httpResponseBytes  prometheus.Counter
+	fetchCalls         prometheus.Counter
+	fetchBytes         prometheus.Counter
+	cacheHits          prometheus.Counter
+}
+
+func newDaemonMetrics(address string) (*daemonMetrics, error) {
+	m := &daemonMetrics{
+		retrievals: prometheus.NewCounter(prometheus.CounterOpts{
+			Name: "lassie_retrievals_total",
+			Help: "Number of retrievals started, including requests made via HTTP.",
+		}),
+		activeRetrievals: prometheus.NewGauge(prometheus.GaugeOpts{
+			Name: "lassie_active_retrievals",
+			Help: "Number of retrievals in progress.",
+		}),
+		failedRetrievals: prometheus.NewCounter(prometheus.CounterOpts{
+			Name: "lassie_failed_retrievals_total",
+			Help: "Number of retrievals that failed.",
+		}),
+		providerRetrievals: prometheus.NewCounterVec(prometheus.CounterOpts{
+			Name: "lassie_provider_retrievals_total",
+			Help: "Number of retrievals from storage providers by protocol and result (success or failure).",
+		}, []string{"protocol", "result"}),
+		receivedBytes: prometheus.NewCounterVec(prometheus.CounterOpts{
+			Name: "lassie_received_bytes_total",
+			Help: "Number of bytes received from storage providers by protocol.",
+		}, []string{"protocol"}),
+		timeToFirstByte: prometheus.NewHistogramVec(prometheus.HistogramOpts{
+			Name:    "lassie_time_to_first_byte_seconds",
+			Help:    "Time from the start of the retrieval from a storage provider to the first byte received.",
+			Buckets: prometheus.ExponentialBuckets(0.05, 2, 10),
+		}, []string{"protocol"}),
+		httpRequests: prometheus.NewCounterVec(prometheus.CounterOpts{
+			Name: "lassie_http_requests_total",
+			Help: "Number of `/ipfs/` requests served over HTTP by status code.",
+		}, []string{"code"}),
+		httpResponseBytes: prometheus.NewCounter(prometheus.CounterOpts{
+			Name: "lassie_http_response_bytes_total",
+			Help: "Number of response bytes written to HTTP clients.",
+		}),
+		fetchCalls: prometheus.NewCounter(prometheus.CounterOpts{
+			Name: "lassie_fetch_calls_total",
+			Help: "Number of in-process retrievals started without going through the HTTP server.",
+		}),
+		fetchBytes: prometheus.NewCounter(prometheus.CounterOpts{
+			Name: "lassie_fetch_bytes_total",
+			Help: "Number of CAR bytes passed to in-process retrievals.",
+		}),
+		cacheHits: prometheus.NewCounter(prometheus.CounterOpts{
+			Name: "lassie_cache_hits_total",
+			Help: "Number of retrievals served from the block cache.",
+		}),
+	}
+
+	registry := prometheus.NewRegistry()
+	registry.MustRegister(
+		m.retrievals,
+		m.activeRetrievals,
+		m.failedRetrievals,
+		m.providerRetrievals,
+		m.receivedBytes,
+		m.timeToFirstByte,
+		m.httpRequests,
+		m.httpResponseBytes,
+		m.fetchCalls,
+		m.fetchBytes,
+		m.cacheHits,
+	)
+
+	listener, err := net.Listen("tcp", address)
+	if err != nil {
+		return nil, err
+	}
+
+	mux := http.NewServeMux()
+	mux.Handle("/metrics", promhttp.HandlerFor(registry, promhttp.HandlerOpts{}))
+	m.listener = listener
+	m.server = &http.Server{
+		Handler:           mux,
+		ReadHeaderTimeout: 10 * time.Second,
+	}
+
+	go func() {
+		err := m.server.Serve(listener)
+		if err != nil && !errors.Is(err, http.ErrServerClosed) {
+			debug("METRICS SERVER FAILED:", err)
+		}
+	}()
+
+	return m, nil
+}
+
+func (m *daemonMetrics) port() (uint16, error) {
+	addr, ok := m.listener.Addr().(*net.TCPAddr)
+	if !ok {
+		return 0, fmt.Errorf("unexpected metrics server address `%s`", m.listener.Addr())
+	}
+	return uint16(addr.Port), nil
+}
+
+func (m *daemonMetrics) close() {
+	if err := m.server.Close(); err != nil {
+		debug("CANNOT CLOSE METRICS SERVER:", err)
+	}
+	// The server closes only the listener it is serving, Serve may not have been called yet
+	if err := m.listener.Close(); err != nil && !errors.Is(err, net.ErrClosed) {
+		debug("CANNOT CLOSE METRICS LISTENER:", err)
+	}
+}
+
+func (m *daemonMetrics) observe(event types.RetrievalEvent) {
+	switch e := event.(type) {
+	case events.StartedFetchEvent:
+		m.retrievals.Inc()
+		m.activeRetrievals.Inc()
+	case events.FinishedEvent:
+		m.activeRetrievals.Dec()
+	case events.FailedEvent:
+		m.failedRetrievals.Inc()
+	case events.FirstByteEvent:
+		m.timeToFirstByte.WithLabelValues(protocolName(e.Protocol())).Observe(e.Duration().Seconds())
+	case events.FailedRetrievalEvent:
+		m.providerRetrievals.WithLabelValues(protocolName(e.Protocol()), "failure").Inc()
+	case events.SucceededEvent:
+		protocol := protocolName(e.Protocol())
+		m.providerRetrievals.WithLabelValues(protocol, "success").Inc()
+		m.receivedBytes.WithLabelValues(protocol).Add(float64(e.ReceivedBytesSize()))
+	}
+}
@@ -0,0 +1,201 @@
+package main
+
+import (
+	"crypto/tls"
+	"errors"
+	"fmt"
+	"net"
+	"net/http"
+	"os"
+	"strconv"
+	"sync/atomic"
+	"syscall"
+	"time"
+
+	servertiming "github.com/mitchellh/go-server-timing"
+)
+
+// daemonServer is the HTTP server of a daemon serving the Trustless Gateway API of Lassie on a
+// TCP or Unix domain socket listener.
+type daemonServer struct {
+	listener net.Listener
+	server   *http.Server
+	// certificate is nil when the server does not use TLS
+	certificate *atomic.Pointer[tls.Certificate]
+}
+
+// newDaemonHandler creates the handler serving the routes of Lassie's HTTP server. The `/ipfs/`
+// requests are authorized and limited by the access tokens, and counted in `metrics` unless it's
+// nil. The `/ping` health check is open to everybody, like in Lassie.
+func newDaemonHandler(tokens *accessTokens, metrics *daemonMetrics) http.Handler {
+	var ipfsHandler http.Handler = tokens
+	if metrics != nil {
+		ipfsHandler = countRequests(ipfsHandler, metrics)
+	}
+	mux := http.NewServeMux()
+	mux.HandleFunc("/ping", func(res http.ResponseWriter, req *http.Request) {
+		_, _ = res.Write([]byte("pong"))
+	})
+	mux.Handle("/ipfs/", ipfsHandler)
+	return servertiming.Middleware(mux, nil)
+}
+
+// countRequests records the status code and the response bytes of every request in the metrics,
+// including requests rejected by the access tokens.
+func countRequests(handler http.Handler, metrics *daemonMetrics) http.Handler {
+	return http.HandlerFunc(func(res http.ResponseWriter, req *http.Request) {
+		writer := &metricsWriter{ResponseWriter: res, metrics: metrics}
+		handler.ServeHTTP(writer, req)
+		status := writer.status
+		if status == 0 {
+			// net/http sends 200 when the handler does not write anything
+			status = http.StatusOK
+		}
+		metrics.httpRequests.WithLabelValues(strconv.Itoa(status)).Inc()
+	})
+}
+
+// metricsWriter counts the bytes of the response body as they are written and remembers the
+// status code of the response.
+type metricsWriter struct {
+	http.ResponseWriter
+	metrics *daemonMetrics
+	status  int
+}
+
+func (w *metricsWriter) WriteHeader(status int) {
+	if w.status == 0 {
+		w.status = status
+	}
+	w.ResponseWriter.WriteHeader(status)
+}
+
+func (w *metricsWriter) Write(data []byte) (int, error) {
+	if w.status == 0 {
+		w.status = http.StatusOK
+	}
+	n, err := w.ResponseWriter.Write(data)
+	w.metrics.httpResponseBytes.Add(float64(n))
+	return n, err
+}
+
+// Flush keeps streaming responses working, see countingWriter.
+func (w *metricsWriter) Flush() {
+	if flusher, ok := w.ResponseWriter.(http.Flusher); ok {
+		flusher.Flush()
+	}
+}
+
+// Unwrap gives http.ResponseController access to the original writer.
+func (w *metricsWriter) Unwrap() http.ResponseWriter {
+	return w.ResponseWriter
+}
+
+func newTCPServer(host string, port uint16, handler http.Handler) (*daemonServer, error) {
+	listener, err := net.Listen("tcp", net.JoinHostPort(host, strconv.Itoa(int(port))))
+	if err != nil {
+		return nil, err
+	}
+	return newDaemonServer(listener, handler), nil
+}
+
+// newUnixServer listens on a Unix domain socket at `path` and applies `mode` to the socket file.
+// A socket file left behind by a process that is no longer running is removed first.
+func newUnixServer(path string, mode os.FileMode, handler http.Handler) (*daemonServer, error) {
+	if err := removeStaleSocket(path); err != nil {
+		return nil, err
+	}
+	listener, err := net.Listen("unix", path)
+	if err != nil {
+		return nil, err
+	}
+	if err := os.Chmod(path, mode); err != nil {
+		listener.Close()
+		return nil, fmt.Errorf("cannot set permissions of socket `%s`: %w", path, err)
+	}
+	return newDaemonServer(listener, handler), nil
+}
+
+func removeStaleSocket(path string) error {
+	info, err := os.Lstat(path)
+	if errors.Is(err, os.ErrNotExist) {
+		return nil
+	}
+	if err != nil {
+		return err
+	}
+	if info.Mode().Type() != os.ModeSocket {
+		return fmt.Errorf("`%s` exists and is not a socket", path)
+	}
+
+	conn, err := net.DialTimeout("unix", path, time.Second)
+	if err == nil {
+		conn.Close()
+		return fmt.Errorf("socket `%s` is in use by another process", path)
+	}
+	if !errors.Is(err, syscall.ECONNREFUSED) {
+		return fmt.Errorf("cannot check whether socket `%s` is in use: %w", path, err)
+	}
+
+	debug("REMOVING STALE SOCKET", path)
+	return os.Remove(path)
+}
+
+func newDaemonServer(listener net.Listener, handler http.Handler) *daemonServer {
+	return &daemonServer{
+		listener: listener,
+		server: &http.Server{
+			Handler:           handler,
+			ReadHeaderTimeout: 10 * time.Second,
+		},
+	}
+}
+
+// enableTLS terminates TLS connections on the listener using the certificate, which can be
+// replaced later via setCertificate. It must be called before start.
+func (s *daemonServer) enableTLS(cert *tls.Certificate) {
+	s.certificate = &atomic.Pointer[tls.Certificate]{}
+	s.certificate.Store(cert)
+	config := &tls.Config{MinVersion: tls.VersionTLS12}
+	config.GetCertificate = func(*tls.ClientHelloInfo) (*tls.Certificate, error) {
+		return s.certificate.Load(), nil
+	}
+	s.listener = tls.NewListener(s.listener, config)
+}
+
+// setCertificate replaces the certificate used for new TLS connections. Connections that are
+// already established keep using the previous certificate.
+func (s *daemonServer) setCertificate(cert *tls.Certificate) error {
+	if s.certificate == nil {
+		return errors.New("the daemon was started without TLS")
+	}
+	s.certificate.Store(cert)
+	return nil
+}
+
+// port returns the TCP port of the listener, or zero when listening on a Unix domain socket.
+func (s *daemonServer) port() uint16 {
+	if addr, ok := s.listener.Addr().(*net.TCPAddr); ok {
+		return uint16(addr.Port)
+	}
+	return 0
+}
+
+// start serves requests until the server is closed.
+func (s *daemonServer) start() error {
+	err := s.server.Serve(s.listener)
+	if errors.Is(err, http.ErrServerClosed) {
+		return nil
+	}
+	return err
+}
+
+func (s *daemonServer) close() error {
+	err := s.server.Close()
+	// The server closes only the listeners it is serving, start() may not have been called yet.
+	// Closing a Unix listener removes the socket file.
+	if closeErr := s.listener.Close(); err == nil && !errors.Is(closeErr, net.ErrClosed) {
+		err = closeErr
+	}
+	return err
+}
@@ -0,0 +1,87 @@
+package main
+
+import (
+	"crypto/tls"
+	"crypto/x509"
+	"encoding/pem"
+	"errors"
+	"fmt"
+	"strings"
+)
+
+// tlsError reports which part of the TLS configuration is invalid, using the errorKindTls*
+// constants.
+type tlsError struct {
+	kind int
+	err  error
+}
+
+func (e *tlsError) Error() string {
+	return e.err.Error()
+}
+
+func (e *tlsError) Unwrap() error {
+	return e.err
+}
+
+// loadCertificate parses the PEM-encoded certificate chain and private key. Unlike
+// tls.X509KeyPair, it tells apart problems with the certificate, the key and a key that does not
+// belong to the certificate.
+func loadCertificate(certPEM []byte, keyPEM []byte) (*tls.Certificate, error) {
+	if err := checkCertificates(certPEM); err != nil {
+		return nil, &tlsError{errorKindTlsCertificate, err}
+	}
+	if err := checkPrivateKey(keyPEM); err != nil {
+		return nil, &tlsError{errorKindTlsKey, err}
+	}
+	cert, err := tls.X509KeyPair(certPEM, keyPEM)
+	if err != nil {
+		return nil, &tlsError{errorKindTlsKeyMismatch, err}
+	}
+	return &cert, nil
+}
+
+func checkCertificates(certPEM []byte) error {
+	found := false
+	for rest := certPEM; ; {
+		var block *pem.Block
+		block, rest = pem.Decode(rest)
+		if block == nil {
+			break
+		}
+		if block.Type != "CERTIFICATE" {
+			continue
+		}
+		if _, err := x509.ParseCertificate(block.Bytes); err != nil {
+			return fmt.Errorf("cannot parse certificate: %w", err)
+		}
+		found = true
+	}
+	if !found {
+		return errors.New("no CERTIFICATE block found in PEM data")
+	}
+	return nil
+}
+
+func checkPrivateKey(keyPEM []byte) error {
+	for rest := keyPEM; ; {
+		var block *pem.Block
+		block, rest = pem.Decode(rest)
+		if block == nil {
+			return errors.New("no PRIVATE KEY block found in PEM data")
+		}
+		if block.Type != "PRIVATE KEY" && !strings.HasSuffix(block.Type, " PRIVATE KEY") {
+			continue
+		}
+		if _, err := x509.ParsePKCS8PrivateKey(block.Bytes); err == nil {
+			return nil
+		}
+		if _, err := x509.ParsePKCS1PrivateKey(block.Bytes); err == nil {
+			return nil
+		}
+		if _, err := x509.ParseECPrivateKey(block.Bytes); err == nil {
+			return nil
+		}
+		return fmt.Errorf("cannot parse %s block", block.Type)
+	}
+}
@@ -0,0 +1,63 @@
+/// An access token accepted by the daemon, together with the limits applied to the requests
+/// authorized by it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AccessToken {
+    /// The token clients send in the `Authorization: Bearer {token}` header.
+    pub token: String,
+
+    /// The limits applied to requests authorized by this token. No limits are enforced by
+    /// default.
+    pub policy: AccessTokenPolicy,
+}
+
+impl AccessToken {
+    /// Create an access token without any limits.
+    pub fn new(token: impl Into<String>) -> Self {
+        AccessToken {
+            token: token.into(),
+            policy: AccessTokenPolicy::default(),
+        }
+    }
+
+    /// Set the limits applied to requests authorized by this token.
+    #[must_use]
+    pub fn with_policy(mut self, policy: AccessTokenPolicy) -> Self {
+        self.policy = policy;
+        self
+    }
+}
+
+/// Quotas and rate limits of an access token. `None` means the limit is not enforced.
+///
+/// Requests exceeding a limit are rejected with `429 Too Many Requests` and a `Retry-After`
+/// header telling the client how many seconds to wait.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct AccessTokenPolicy {
+    /// Maximum number of retrievals running at the same time.
+    pub max_concurrent_retrievals: Option<u32>,
+
+    /// Maximum number of requests started within the last 60 seconds.
+    pub requests_per_minute: Option<u32>,
+
+    /// Maximum number of response bytes per UTC day. The limit is checked when a request starts,
+    /// the response that crosses the limit is sent in full.
+    pub bytes_per_day: Option<u64>,
+
+    /// Maximum number of blocks to retrieve per request, overrides
+    /// [`DaemonConfig::max_blocks`](crate::DaemonConfig::max_blocks).
+    pub max_blocks: Option<u64>,
+}
+
+/// Resources consumed by the requests authorized by an access token, see
+/// [`Daemon::access_token_usage`](crate::Daemon::access_token_usage).
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct AccessTokenUsage {
+    /// Number of retrievals that are currently running.
+    pub active_retrievals: u32,
+
+    /// Number of requests started within the last 60 seconds.
+    pub requests_last_minute: u32,
+
+    /// Number of response bytes sent since the start of the current UTC day.
+    pub bytes_today: u64,
+}
@@ -0,0 +1,35 @@
+use std::fmt::{Display, Formatter};
+use std::net::IpAddr;
+
+#[derive(Debug, PartialEq, Clone)]
+#[non_exhaustive]
+pub enum AccessTokenError {
+    ContainsNullByte(String),
+    IsEmpty,
+    /// A limit of the token policy is zero, use `None` to disable the limit.
+    LimitIsZero(&'static str),
+    /// The daemon listens on an address that is not loopback, at least one token is required.
+    Required(IpAddr),
+    Lassie(String),
+}
+
+impl Display for AccessTokenError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+        write!(f, "failed to set access tokens: ")?;
+        match self {
+            AccessTokenError::ContainsNullByte(token) => f.write_fmt(format_args!(
+                "null bytes are not allowed in the access token (value: {token:?})",
+            )),
+            AccessTokenError::IsEmpty => f.write_str("access tokens must not be empty"),
+            AccessTokenError::LimitIsZero(limit) => f.write_fmt(format_args!(
+                "{limit} of an access token must be greater than zero",
+            )),
+            AccessTokenError::Required(addr) => f.write_fmt(format_args!(
+                "an access token is required when listening on address {addr}, which is not a loopback address",
+            )),
+            AccessTokenError::Lassie(msg) => f.write_str(msg),
+        }
+    }
+}
+
+impl std::error::Error for AccessTokenError {}
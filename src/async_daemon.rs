@@ -0,0 +1,143 @@
+use std::io;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+use cid::Cid;
+use tokio::io::{AsyncRead, ReadBuf};
+use tokio::sync::mpsc;
+
+use crate::fetch::{cancel_fetch, create_fetch, run_fetch, Message, CHANNEL_CAPACITY};
+use crate::{Daemon, DaemonConfig, DaemonStatus, FetchError, FetchOptions, StartError, StopError};
+
+impl Daemon {
+    /// Start the daemon without blocking the async runtime.
+    ///
+    /// The blocking work is executed on tokio's blocking thread pool.
+    ///
+    /// # Errors
+    ///
+    /// See [`Daemon::start`].
+    pub async fn start_async(config: DaemonConfig) -> Result<Self, StartError> {
+        run_blocking(move || Daemon::start(config)).await
+    }
+
+    /// Stop the daemon without blocking the async runtime.
+    ///
+    /// Dropping the daemon blocks the current thread until Lassie exits, you should call this
+    /// function instead when running inside an async runtime.
+    ///
+    /// # Errors
+    ///
+    /// See [`Daemon::stop`].
+    pub async fn shutdown(self) -> Result<(), StopError> {
+        run_blocking(move || self.stop()).await
+    }
+
+    /// Wait until the Lassie HTTP handler exits without blocking the async runtime, then return
+    /// the final status.
+    pub async fn wait_async(&self) -> DaemonStatus {
+        let status = self.status.clone();
+        run_blocking(move || status.wait()).await
+    }
+
+    /// Async version of [`Daemon::fetch`].
+    ///
+    /// The retrieval runs on tokio's blocking thread pool, the returned stream yields the CAR bytes
+    /// as they arrive.
+    ///
+    /// # Panics
+    ///
+    /// This function panics when called outside of a tokio runtime.
+    #[must_use]
+    pub fn fetch_async(&self, cid: Cid, options: FetchOptions) -> AsyncCarStream {
+        AsyncCarStream::start(self.handle, cid, options)
+    }
+}
+
+async fn run_blocking<T: Send + 'static>(f: impl FnOnce() -> T + Send + 'static) -> T {
+    match tokio::task::spawn_blocking(f).await {
+        Ok(value) => value,
+        Err(err) => std::panic::resume_unwind(err.into_panic()),
+    }
+}
+
+/// A stream of CAR bytes produced by [`Daemon::fetch_async`].
+///
+/// When the retrieval fails, the stream reports the [`FetchError`] wrapped in `std::io::Error`
+/// of kind [`std::io::ErrorKind::Other`]. Dropping the stream aborts the retrieval.
+pub struct AsyncCarStream {
+    fetch_id: u64,
+    receiver: mpsc::Receiver<Message>,
+    chunk: Vec<u8>,
+    offset: usize,
+    finished: bool,
+}
+
+impl AsyncCarStream {
+    fn start(handle: u64, cid: Cid, options: FetchOptions) -> Self {
+        let fetch_id = create_fetch();
+        let (sender, receiver) = mpsc::channel(CHANNEL_CAPACITY);
+        tokio::task::spawn_blocking(move || {
+            let result = run_fetch(handle, fetch_id, &cid, &options, &mut |data| {
+                sender.blocking_send(Message::Data(data.to_vec())).is_ok()
+            });
+            // The receiver may have been dropped already, there is nobody to report the result to
+            let _ = sender.blocking_send(Message::Done(result));
+        });
+
+        AsyncCarStream {
+            fetch_id,
+            receiver,
+            chunk: Vec::new(),
+            offset: 0,
+            finished: false,
+        }
+    }
+}
+
+impl Drop for AsyncCarStream {
+    fn drop(&mut self) {
+        // The blocking task keeps running while Lassie waits for providers, see `CarStream`
+        cancel_fetch(self.fetch_id);
+    }
+}
+
+impl AsyncRead for AsyncCarStream {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        while self.offset >= self.chunk.len() {
+            if self.finished {
+                return Poll::Ready(Ok(()));
+            }
+
+            match self.receiver.poll_recv(cx) {
+                Poll::Pending => return Poll::Pending,
+                Poll::Ready(Some(Message::Data(data))) => {
+                    self.chunk = data;
+                    self.offset = 0;
+                }
+                Poll::Ready(Some(Message::Done(result))) => {
+                    self.finished = true;
+                    if let Err(err) = result {
+                        return Poll::Ready(Err(io::Error::other(err)));
+                    }
+                }
+                Poll::Ready(None) => {
+                    self.finished = true;
+                    return Poll::Ready(Err(io::Error::other(FetchError::Lassie(
+                        "the retrieval task exited unexpectedly".to_string(),
+                    ))));
+                }
+            }
+        }
+
+        let this = &mut *self;
+        let len = buf.remaining().min(this.chunk.len() - this.offset);
+        buf.put_slice(&this.chunk[this.offset..this.offset + len]);
+        this.offset += len;
+        Poll::Ready(Ok(()))
+    }
+}
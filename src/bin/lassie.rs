@@ -0,0 +1,293 @@
+//! Command-line tool for debugging retrievals with the embedded Lassie daemon.
+//!
+//! Run `lassie --help` for the list of commands and options.
+
+use std::ffi::OsStr;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::net::IpAddr;
+use std::path::PathBuf;
+use std::sync::mpsc;
+use std::time::Duration;
+
+use clap::{Args, Parser, Subcommand};
+use lassie::car::CarReader;
+use lassie::{
+    AccessToken, Cid, Daemon, DaemonConfig, DaemonStatus, DagScope, FetchOptions, MetricsConfig,
+    PemSource, Protocol, Provider, TlsConfig, UnixSocketConfig,
+};
+
+type CliResult = Result<(), Box<dyn std::error::Error>>;
+
+#[derive(Parser)]
+#[command(
+    version,
+    about = "Retrieve content from IPFS and Filecoin using Lassie"
+)]
+struct Cli {
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Retrieve a CID and write it to a CAR file or extract it to a directory
+    Fetch(FetchArgs),
+    /// Run the Lassie HTTP daemon in the foreground until interrupted
+    Serve(ServeArgs),
+}
+
+#[derive(Args)]
+struct FetchArgs {
+    /// The CID to retrieve, optionally followed by a path, e.g. `bafy.../some/file.txt`
+    target: String,
+
+    /// Where to write the content. Paths ending with `.car` receive the CAR stream, `-` writes
+    /// the CAR stream to stdout, any other path is the file or directory where the
+    /// content is extracted to.
+    #[arg(short, long)]
+    output: PathBuf,
+
+    /// Which blocks of the DAG to retrieve: all, entity or block
+    #[arg(long, default_value = "all", value_parser = parse_dag_scope)]
+    scope: DagScope,
+
+    /// Maximum number of blocks to retrieve
+    #[arg(long)]
+    max_blocks: Option<u64>,
+
+    #[command(flatten)]
+    daemon: DaemonArgs,
+}
+
+#[derive(Args)]
+struct ServeArgs {
+    /// IP address where to listen, e.g. `::1` or `0.0.0.0`. Addresses other than loopback
+    /// require `--access-token`
+    #[arg(long, default_value = "127.0.0.1")]
+    listen_addr: IpAddr,
+
+    /// Port where to listen, a free port is chosen by default
+    #[arg(long, default_value_t = 0)]
+    port: u16,
+
+    /// Listen on this Unix domain socket instead of a TCP port, the socket is accessible to the
+    /// current user only
+    #[arg(long, conflicts_with_all = ["listen_addr", "port"])]
+    unix_socket: Option<PathBuf>,
+
+    /// Require requests to send `Authorization: Bearer {token}`, can be repeated
+    #[arg(long = "access-token")]
+    access_tokens: Vec<String>,
+
+    /// Serve the HTTP API over TLS using this PEM-encoded certificate chain
+    #[arg(long, requires = "tls_key")]
+    tls_cert: Option<PathBuf>,
+
+    /// PEM-encoded private key of the TLS certificate
+    #[arg(long, requires = "tls_cert")]
+    tls_key: Option<PathBuf>,
+
+    /// Serve Prometheus metrics on this port of the listen address
+    #[arg(long)]
+    metrics_port: Option<u16>,
+
+    /// Maximum number of blocks to retrieve per request
+    #[arg(long)]
+    max_blocks: Option<u64>,
+
+    #[command(flatten)]
+    daemon: DaemonArgs,
+}
+
+#[derive(Args)]
+struct DaemonArgs {
+    /// Retrieve from this provider (multiaddr including the peer ID) instead of asking the
+    /// indexer, can be repeated
+    #[arg(long = "provider")]
+    providers: Vec<String>,
+
+    /// Retrieval protocol to use: bitswap, graphsync or http, can be repeated
+    #[arg(long = "protocol", value_parser = parse_protocol)]
+    protocols: Vec<Protocol>,
+
+    /// Timeout for the entire retrieval, in seconds
+    #[arg(long)]
+    timeout: Option<u64>,
+
+    /// Timeout for receiving data from a provider, in seconds
+    #[arg(long)]
+    provider_timeout: Option<u64>,
+}
+
+impl DaemonArgs {
+    fn into_config(self) -> DaemonConfig {
+        DaemonConfig {
+            providers: self.providers.into_iter().map(Provider::new).collect(),
+            protocols: (!self.protocols.is_empty()).then(|| self.protocols.into_iter().collect()),
+            global_timeout: self.timeout.map(Duration::from_secs),
+            provider_timeout: self.provider_timeout.map(Duration::from_secs),
+            ..DaemonConfig::default()
+        }
+    }
+}
+
+fn main() {
+    env_logger::Builder::from_env(env_logger::Env::default().default_filter_or("warn")).init();
+
+    let result = match Cli::parse().command {
+        Command::Fetch(args) => fetch(args),
+        Command::Serve(args) => serve(args),
+    };
+    if let Err(err) = result {
+        eprintln!("Error: {err}");
+        std::process::exit(1);
+    }
+}
+
+fn fetch(args: FetchArgs) -> CliResult {
+    let (cid, path) = parse_target(&args.target)?;
+    let daemon = Daemon::start(args.daemon.into_config())?;
+
+    let extract =
+        args.output.as_os_str() != "-" && args.output.extension() != Some(OsStr::new("car"));
+    let options = FetchOptions {
+        path: path.clone(),
+        dag_scope: args.scope,
+        max_blocks: args.max_blocks,
+        // The UnixFS extractor streams the content and needs duplicate blocks
+        duplicates: extract,
+        ..FetchOptions::default()
+    };
+    let mut stream = daemon.fetch(cid, options);
+
+    if extract {
+        let car = CarReader::new(stream)?;
+        let progress =
+            lassie::unixfs::extract_path(car, path.as_deref().unwrap_or(""), &args.output)?;
+        eprintln!(
+            "Extracted {} files and {} directories ({} bytes) to {}",
+            progress.files,
+            progress.directories,
+            progress.bytes,
+            args.output.display()
+        );
+    } else if args.output.as_os_str() == "-" {
+        let mut stdout = std::io::stdout().lock();
+        std::io::copy(&mut stream, &mut stdout)?;
+        stdout.flush()?;
+    } else {
+        let mut file = BufWriter::new(File::create(&args.output)?);
+        let bytes = std::io::copy(&mut stream, &mut file)?;
+        file.flush()?;
+        eprintln!("Wrote {bytes} bytes to {}", args.output.display());
+    }
+
+    daemon.stop()?;
+    Ok(())
+}
+
+fn serve(args: ServeArgs) -> CliResult {
+    let daemon = Daemon::start(DaemonConfig {
+        listen_addr: args.listen_addr,
+        port: args.port,
+        unix_socket: args
+            .unix_socket
+            .map(|path| UnixSocketConfig { path, mode: 0o600 }),
+        access_tokens: args
+            .access_tokens
+            .into_iter()
+            .map(AccessToken::new)
+            .collect(),
+        tls: args
+            .tls_cert
+            .zip(args.tls_key)
+            .map(|(cert, key)| TlsConfig {
+                cert: PemSource::Path(cert),
+                key: PemSource::Path(key),
+            }),
+        metrics: args.metrics_port.map(|port| MetricsConfig { port }),
+        max_blocks: args.max_blocks,
+        ..args.daemon.into_config()
+    })?;
+
+    let scheme = if daemon.tls_enabled() {
+        "https"
+    } else {
+        "http"
+    };
+    if let Some(path) = daemon.unix_socket() {
+        println!("Lassie is listening on {}", path.display());
+    } else {
+        println!("Lassie is listening on {scheme}://{}", daemon.local_addr());
+    }
+    if let Some(addr) = daemon.metrics_addr() {
+        println!("Metrics are served at http://{addr}/metrics");
+    }
+
+    // The handler is called for SIGINT and SIGTERM (on Unix) or Ctrl+C (on Windows)
+    let (shutdown_sender, shutdown_receiver) = mpsc::channel();
+    ctrlc::set_handler(move || {
+        let _ = shutdown_sender.send(());
+    })?;
+
+    loop {
+        if shutdown_receiver.try_recv().is_ok() {
+            eprintln!("Shutting down");
+            daemon.stop()?;
+            return Ok(());
+        }
+        if let DaemonStatus::Exited(error) = daemon.wait_timeout(Duration::from_millis(200)) {
+            return match error {
+                Some(msg) => Err(msg.into()),
+                None => Ok(()),
+            };
+        }
+    }
+}
+
+/// Split `{cid}/{path}` into the CID and the optional path.
+fn parse_target(target: &str) -> Result<(Cid, Option<String>), String> {
+    let target = target.strip_prefix("/ipfs/").unwrap_or(target);
+    let (cid, path) = match target.split_once('/') {
+        Some((cid, path)) => (cid, Some(path.trim_matches('/').to_string())),
+        None => (target, None),
+    };
+    let cid = cid
+        .parse()
+        .map_err(|err| format!("invalid CID `{cid}`: {err}"))?;
+    Ok((cid, path.filter(|p| !p.is_empty())))
+}
+
+fn parse_dag_scope(value: &str) -> Result<DagScope, String> {
+    [DagScope::All, DagScope::Entity, DagScope::Block]
+        .into_iter()
+        .find(|scope| scope.as_str() == value)
+        .ok_or_else(|| format!("unknown DAG scope `{value}`"))
+}
+
+fn parse_protocol(value: &str) -> Result<Protocol, String> {
+    [Protocol::Bitswap, Protocol::Graphsync, Protocol::Http]
+        .into_iter()
+        .find(|protocol| protocol.as_str() == value)
+        .ok_or_else(|| format!("unknown protocol `{value}`"))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn parses_fetch_target() {
+        let cid: Cid = "bafkreih25dih6ug3xtj73vswccw423b56ilrwmnos4cbwhrceudopdp5sq"
+            .parse()
+            .unwrap();
+        assert_eq!(parse_target(&cid.to_string()), Ok((cid, None)));
+        assert_eq!(
+            parse_target(&format!("/ipfs/{cid}/some/file.txt")),
+            Ok((cid, Some("some/file.txt".to_string())))
+        );
+        assert!(parse_target("not-a-cid/file.txt").is_err());
+    }
+}
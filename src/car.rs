@@ -0,0 +1,410 @@
+//! Reader of [CAR v1](https://ipld.io/specs/transport/car/carv1/) streams verifying the data of
+//! each block against its CID.
+//!
+//! ```no_run
+//! use lassie::car::CarReader;
+//! use lassie::{Daemon, DaemonConfig, FetchOptions};
+//!
+//! let daemon = Daemon::start(DaemonConfig::default()).expect("cannot start Lassie");
+//! let cid = "bafybeib36krhffuh3cupjml4re2wfxldredkir5wti3dttulyemre7xkni".parse().unwrap();
+//! let car = CarReader::new(daemon.fetch(cid, FetchOptions::default())).expect("invalid CAR");
+//! println!("roots: {:?}", car.roots());
+//! for block in car {
+//!     let (cid, data) = block.expect("invalid block");
+//!     println!("{cid}: {} bytes", data.len());
+//! }
+//! ```
+
+use std::io::{ErrorKind, Read};
+
+use cid::Cid;
+use sha2::{Digest, Sha256, Sha512};
+
+pub use crate::car_error::CarError;
+
+/// Sections larger than this are rejected to avoid allocating unbounded amounts of memory.
+/// Lassie does not accept blocks larger than a few megabytes.
+const MAX_SECTION_LEN: u64 = 16 * 1024 * 1024;
+
+// Multihash codes, see https://github.com/multiformats/multicodec/blob/master/table.csv
+const IDENTITY: u64 = 0x00;
+const SHA2_256: u64 = 0x12;
+const SHA2_512: u64 = 0x13;
+
+/// Reads the header and the blocks of a CAR v1 stream, e.g. the response of the `/ipfs/`
+/// endpoint or the stream returned by [`Daemon::fetch`](crate::Daemon::fetch).
+///
+/// Iterating the reader yields `(Cid, data)` pairs. Each block is verified against its CID.
+pub struct CarReader<R: Read> {
+    reader: R,
+    roots: Vec<Cid>,
+    failed: bool,
+}
+
+impl<R: Read> CarReader<R> {
+    /// Read the CAR header from `reader`.
+    ///
+    /// # Errors
+    ///
+    /// This function returns `Err` when the header cannot be read or is not a valid CAR v1 header.
+    pub fn new(mut reader: R) -> Result<Self, CarError> {
+        let header = read_section(&mut reader)?
+            .ok_or_else(|| CarError::InvalidHeader("the stream is empty".to_string()))?;
+        let roots = parse_header(&header)?;
+        Ok(CarReader {
+            reader,
+            roots,
+            failed: false,
+        })
+    }
+
+    /// Root CIDs listed in the CAR header.
+    #[must_use]
+    pub fn roots(&self) -> &[Cid] {
+        &self.roots
+    }
+
+    /// Read the next block, returns `None` at the end of the stream.
+    ///
+    /// # Errors
+    ///
+    /// This function returns `Err` when the block cannot be read, is malformed or its data does
+    /// not match its CID.
+    pub fn next_block(&mut self) -> Result<Option<(Cid, Vec<u8>)>, CarError> {
+        let Some(section) = read_section(&mut self.reader)? else {
+            return Ok(None);
+        };
+
+        let mut cursor = std::io::Cursor::new(&section);
+        let cid = Cid::read_bytes(&mut cursor)
+            .map_err(|err| CarError::InvalidBlock(format!("invalid CID: {err}")))?;
+        // The position is within the section, it always fits into usize
+        let offset = usize::try_from(cursor.position()).unwrap_or(section.len());
+        let data = section[offset..].to_vec();
+
+        verify_block(&cid, &data)?;
+        Ok(Some((cid, data)))
+    }
+}
+
+impl<R: Read> Iterator for CarReader<R> {
+    type Item = Result<(Cid, Vec<u8>), CarError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        // Stop after the first error, the position in the stream is undefined at that point
+        if self.failed {
+            return None;
+        }
+        let result = self.next_block().transpose();
+        if matches!(result, Some(Err(_))) {
+            self.failed = true;
+        }
+        result
+    }
+}
+
+/// Check that the data of the block matches the multihash in its CID.
+///
+/// # Errors
+///
+/// This function returns `Err` when the data does not match, the digest in the CID is truncated,
+/// or the hash function is not supported (only identity, sha2-256 and sha2-512 are supported).
+pub fn verify_block(cid: &Cid, data: &[u8]) -> Result<(), CarError> {
+    let hash = cid.hash();
+    let computed = match hash.code() {
+        IDENTITY => data.to_vec(),
+        SHA2_256 => Sha256::digest(data).to_vec(),
+        SHA2_512 => Sha512::digest(data).to_vec(),
+        code => return Err(CarError::UnsupportedMultihash { cid: *cid, code }),
+    };
+
+    // Multihash allows truncated digests, but a short digest verifies little and an empty one
+    // matches any data
+    let digest = hash.digest();
+    if hash.code() != IDENTITY && digest.len() < computed.len() {
+        return Err(CarError::TruncatedDigest(*cid));
+    }
+
+    if computed == digest {
+        Ok(())
+    } else {
+        Err(CarError::HashMismatch(*cid))
+    }
+}
+
+/// Read a section prefixed with its varint length. Returns `None` at the end of the stream.
+fn read_section(reader: &mut impl Read) -> Result<Option<Vec<u8>>, CarError> {
+    let Some(len) = read_varint(reader)? else {
+        return Ok(None);
+    };
+    if len > MAX_SECTION_LEN {
+        return Err(CarError::InvalidBlock(format!(
+            "section length {len} exceeds the limit of {MAX_SECTION_LEN} bytes"
+        )));
+    }
+
+    // The length is limited by MAX_SECTION_LEN, it always fits into usize
+    let mut section = vec![0; usize::try_from(len).unwrap_or_default()];
+    reader
+        .read_exact(&mut section)
+        .map_err(|err| match err.kind() {
+            ErrorKind::UnexpectedEof => CarError::InvalidBlock("truncated section".to_string()),
+            _ => CarError::Io(err.to_string()),
+        })?;
+    Ok(Some(section))
+}
+
+/// Read an unsigned LEB128 varint. Returns `None` when the stream ends before the first byte.
+fn read_varint(reader: &mut impl Read) -> Result<Option<u64>, CarError> {
+    let mut value: u64 = 0;
+    for ix in 0..10 {
+        let mut byte = [0u8];
+        match reader.read_exact(&mut byte) {
+            Ok(()) => {}
+            Err(err) if err.kind() == ErrorKind::UnexpectedEof && ix == 0 => return Ok(None),
+            Err(err) if err.kind() == ErrorKind::UnexpectedEof => {
+                return Err(CarError::InvalidBlock("truncated varint".to_string()))
+            }
+            Err(err) => return Err(CarError::Io(err.to_string())),
+        }
+
+        value |= u64::from(byte[0] & 0x7f) << (7 * ix);
+        if byte[0] & 0x80 == 0 {
+            return Ok(Some(value));
+        }
+    }
+    Err(CarError::InvalidBlock("varint is too long".to_string()))
+}
+
+/// The subset of CBOR needed to decode the CAR header.
+#[derive(Debug, PartialEq)]
+enum CborValue {
+    Uint(u64),
+    Bytes(Vec<u8>),
+    Text(String),
+    Array(Vec<CborValue>),
+    Map(Vec<(CborValue, CborValue)>),
+    Tag(u64, Box<CborValue>),
+    Other,
+}
+
+/// The CBOR tag used by DAG-CBOR for CIDs.
+const CID_TAG: u64 = 42;
+
+fn parse_header(bytes: &[u8]) -> Result<Vec<Cid>, CarError> {
+    let invalid = |msg: &str| CarError::InvalidHeader(msg.to_string());
+
+    let mut input = bytes;
+    let CborValue::Map(entries) = decode_cbor(&mut input, 0).map_err(CarError::InvalidHeader)?
+    else {
+        return Err(invalid("the header is not a map"));
+    };
+
+    let get = |key: &str| {
+        entries
+            .iter()
+            .find(|(k, _)| *k == CborValue::Text(key.to_string()))
+            .map(|(_, v)| v)
+    };
+
+    match get("version") {
+        Some(CborValue::Uint(1)) => {}
+        Some(CborValue::Uint(version)) => return Err(CarError::UnsupportedVersion(*version)),
+        _ => return Err(invalid("missing version")),
+    }
+
+    let Some(CborValue::Array(roots)) = get("roots") else {
+        return Err(invalid("missing roots"));
+    };
+    roots
+        .iter()
+        .map(|root| match root {
+            // DAG-CBOR prefixes the binary CID with the identity multibase prefix 0x00
+            CborValue::Tag(CID_TAG, value) => match value.as_ref() {
+                CborValue::Bytes(bytes) if bytes.first() == Some(&0) => Cid::try_from(&bytes[1..])
+                    .map_err(|err| CarError::InvalidHeader(format!("invalid root CID: {err}"))),
+                _ => Err(invalid("malformed root CID")),
+            },
+            _ => Err(invalid("root is not a CID")),
+        })
+        .collect()
+}
+
+fn decode_cbor(input: &mut &[u8], depth: usize) -> Result<CborValue, String> {
+    // The CAR header is a flat structure, deeper nesting means the input is malformed
+    if depth > 8 {
+        return Err("the header is nested too deeply".to_string());
+    }
+
+    let initial = take(input, 1)?[0];
+    let major = initial >> 5;
+    let additional = initial & 0x1f;
+    let arg = match additional {
+        0..=23 => u64::from(additional),
+        24 => u64::from(take(input, 1)?[0]),
+        25 => u64::from(u16::from_be_bytes(
+            take(input, 2)?.try_into().unwrap_or_default(),
+        )),
+        26 => u64::from(u32::from_be_bytes(
+            take(input, 4)?.try_into().unwrap_or_default(),
+        )),
+        27 => u64::from_be_bytes(take(input, 8)?.try_into().unwrap_or_default()),
+        _ => return Err(format!("unsupported CBOR additional info {additional}")),
+    };
+    let len = |arg: u64| usize::try_from(arg).map_err(|_| "length is too large".to_string());
+
+    match major {
+        0 => Ok(CborValue::Uint(arg)),
+        2 => Ok(CborValue::Bytes(take(input, len(arg)?)?.to_vec())),
+        3 => String::from_utf8(take(input, len(arg)?)?.to_vec())
+            .map(CborValue::Text)
+            .map_err(|_| "text is not valid UTF-8".to_string()),
+        4 => {
+            // Do not trust the length for preallocation, each item takes at least one byte
+            let mut items = Vec::with_capacity(len(arg)?.min(input.len()));
+            for _ in 0..arg {
+                items.push(decode_cbor(input, depth + 1)?);
+            }
+            Ok(CborValue::Array(items))
+        }
+        5 => {
+            let mut entries = Vec::with_capacity(len(arg)?.min(input.len()));
+            for _ in 0..arg {
+                let key = decode_cbor(input, depth + 1)?;
+                let value = decode_cbor(input, depth + 1)?;
+                entries.push((key, value));
+            }
+            Ok(CborValue::Map(entries))
+        }
+        6 => Ok(CborValue::Tag(
+            arg,
+            Box::new(decode_cbor(input, depth + 1)?),
+        )),
+        // Negative integers, floats and simple values are not used by the CAR header
+        _ => Ok(CborValue::Other),
+    }
+}
+
+fn take<'a>(input: &mut &'a [u8], len: usize) -> Result<&'a [u8], String> {
+    if input.len() < len {
+        return Err("unexpected end of the header".to_string());
+    }
+    let (head, tail) = input.split_at(len);
+    *input = tail;
+    Ok(head)
+}
+
+/// Encoders building CAR streams for the test provider and the unit tests.
+#[cfg(any(test, feature = "testing"))]
+pub(crate) mod encode {
+    use cid::multihash::Multihash;
+    use cid::Cid;
+    use sha2::{Digest, Sha256};
+
+    use super::SHA2_256;
+
+    /// Create a CID v1 with the SHA2-256 hash of the data.
+    pub(crate) fn block_cid(codec: u64, data: &[u8]) -> Cid {
+        // It's safe to call unwrap() here, SHA2-256 digests always fit into the 64-byte multihash
+        let hash = Multihash::wrap(SHA2_256, &Sha256::digest(data)).unwrap();
+        Cid::new_v1(codec, hash)
+    }
+
+    /// Encode the CAR v1 header section with a single root.
+    pub(crate) fn car_header(root: &Cid) -> Vec<u8> {
+        // {"roots": [root], "version": 1} encoded as DAG-CBOR
+        let root_bytes = [&[0u8][..], &root.to_bytes()].concat();
+        let mut header = vec![0xa2, 0x65];
+        header.extend_from_slice(b"roots");
+        header.extend_from_slice(&[0x81, 0xd8, 0x2a, 0x58]);
+        // CIDs are always shorter than 256 bytes
+        header.push(u8::try_from(root_bytes.len()).unwrap_or(u8::MAX));
+        header.extend_from_slice(&root_bytes);
+        header.push(0x67);
+        header.extend_from_slice(b"version");
+        header.push(0x01);
+
+        let mut section = Vec::new();
+        write_varint(&mut section, header.len() as u64);
+        section.extend_from_slice(&header);
+        section
+    }
+
+    /// Encode the CAR section of a block.
+    pub(crate) fn car_block(cid: &Cid, data: &[u8]) -> Vec<u8> {
+        let cid_bytes = cid.to_bytes();
+        let mut section = Vec::new();
+        write_varint(&mut section, (cid_bytes.len() + data.len()) as u64);
+        section.extend_from_slice(&cid_bytes);
+        section.extend_from_slice(data);
+        section
+    }
+
+    pub(crate) fn write_varint(out: &mut Vec<u8>, mut value: u64) {
+        while value >= 0x80 {
+            out.push((value.to_le_bytes()[0] & 0x7f) | 0x80);
+            value >>= 7;
+        }
+        out.push(value.to_le_bytes()[0]);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    const CID: &str = "bafkreih25dih6ug3xtj73vswccw423b56ilrwmnos4cbwhrceudopdp5sq";
+    const CAR: &[u8] = include_bytes!(
+        "../tests/testdata/bafkreih25dih6ug3xtj73vswccw423b56ilrwmnos4cbwhrceudopdp5sq.car"
+    );
+
+    #[test]
+    fn reads_roots_and_blocks() {
+        let cid: Cid = CID.parse().unwrap();
+        let car = CarReader::new(CAR).expect("cannot read CAR header");
+        assert_eq!(car.roots(), &[cid]);
+
+        let blocks = car
+            .collect::<Result<Vec<_>, _>>()
+            .expect("cannot read CAR blocks");
+        assert_eq!(blocks.len(), 1);
+        assert_eq!(blocks[0].0, cid);
+        assert!(blocks[0].1.starts_with(b"lapidary"));
+    }
+
+    #[test]
+    fn rejects_block_not_matching_its_cid() {
+        let mut car = CAR.to_vec();
+        let last = car.len() - 1;
+        car[last] ^= 0xff;
+
+        let result = CarReader::new(car.as_slice())
+            .expect("cannot read CAR header")
+            .next_block();
+        assert_eq!(result, Err(CarError::HashMismatch(CID.parse().unwrap())));
+    }
+
+    #[test]
+    fn rejects_empty_digest() {
+        let hash = cid::multihash::Multihash::wrap(SHA2_256, &[]).unwrap();
+        let cid = Cid::new_v1(0x55, hash);
+        assert_eq!(
+            verify_block(&cid, b"any data"),
+            Err(CarError::TruncatedDigest(cid))
+        );
+    }
+
+    #[test]
+    fn rejects_truncated_block() {
+        let mut reader = CarReader::new(&CAR[..CAR.len() - 1]).expect("cannot read CAR header");
+        assert!(
+            matches!(reader.next(), Some(Err(CarError::InvalidBlock(_)))),
+            "truncated block should be rejected"
+        );
+        assert!(
+            reader.next().is_none(),
+            "the iterator should stop after an error"
+        );
+    }
+}
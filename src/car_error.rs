@@ -0,0 +1,47 @@
+use std::fmt::{Display, Formatter};
+
+use cid::Cid;
+
+#[derive(Debug, PartialEq, Clone)]
+#[non_exhaustive]
+pub enum CarError {
+    /// Reading from the underlying stream failed.
+    Io(String),
+    /// The CAR header is malformed.
+    InvalidHeader(String),
+    /// The CAR version is not 1.
+    UnsupportedVersion(u64),
+    /// A block section is malformed or truncated.
+    InvalidBlock(String),
+    /// The block CID uses a hash function we cannot verify.
+    UnsupportedMultihash { cid: Cid, code: u64 },
+    /// The block data does not match the hash in its CID.
+    HashMismatch(Cid),
+    /// The CID of the block holds a digest shorter than the output of its hash function.
+    TruncatedDigest(Cid),
+}
+
+impl Display for CarError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+        write!(f, "failed to read CAR stream: ")?;
+        match self {
+            CarError::Io(msg) => f.write_str(msg),
+            CarError::InvalidHeader(msg) => f.write_fmt(format_args!("invalid header: {msg}")),
+            CarError::UnsupportedVersion(version) => {
+                f.write_fmt(format_args!("unsupported CAR version {version}"))
+            }
+            CarError::InvalidBlock(msg) => f.write_fmt(format_args!("invalid block: {msg}")),
+            CarError::UnsupportedMultihash { cid, code } => f.write_fmt(format_args!(
+                "cannot verify block {cid}, unsupported multihash code 0x{code:x}"
+            )),
+            CarError::HashMismatch(cid) => f.write_fmt(format_args!(
+                "the data of block {cid} does not match its hash"
+            )),
+            CarError::TruncatedDigest(cid) => f.write_fmt(format_args!(
+                "cannot verify block {cid}, its CID holds a truncated digest"
+            )),
+        }
+    }
+}
+
+impl std::error::Error for CarError {}
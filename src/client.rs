@@ -0,0 +1,470 @@
+//! Typed HTTP client for the
+//! [Trustless Gateway](https://specs.ipfs.tech/http-gateways/trustless-gateway/) API served by
+//! the daemon.
+//!
+//! ```no_run
+//! use std::io::Read;
+//! use lassie::client::Client;
+//! use lassie::{Daemon, DaemonConfig, DagScope};
+//!
+//! let daemon = Daemon::start(DaemonConfig::default()).expect("cannot start Lassie");
+//! let cid = "bafybeib36krhffuh3cupjml4re2wfxldredkir5wti3dttulyemre7xkni".parse().unwrap();
+//! let mut response = Client::new(&daemon)
+//!     .request(cid)
+//!     .path("some/file.txt")
+//!     .dag_scope(DagScope::Entity)
+//!     .send()
+//!     .expect("cannot retrieve the content");
+//!
+//! let mut car = Vec::new();
+//! response.read_to_end(&mut car).expect("cannot read the response");
+//! ```
+
+use std::fmt::Write;
+use std::io::Read;
+use std::net::{Ipv4Addr, Ipv6Addr, SocketAddr};
+
+use cid::Cid;
+
+pub use crate::client_error::ClientError;
+use crate::{ByteRange, Daemon, DagScope, Protocol};
+
+/// Block order in the CAR stream, see
+/// [IPIP-412](https://specs.ipfs.tech/ipips/ipip-0412/).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CarOrder {
+    /// Blocks are in depth-first traversal order.
+    Dfs,
+    /// The order of blocks is not specified.
+    Unknown,
+}
+
+impl CarOrder {
+    /// The name used for this order in the `order` parameter of the CAR content type.
+    #[must_use]
+    pub fn as_str(self) -> &'static str {
+        match self {
+            CarOrder::Dfs => "dfs",
+            CarOrder::Unknown => "unk",
+        }
+    }
+}
+
+/// A client making requests to the HTTP server of a Lassie daemon.
+#[derive(Debug, Clone)]
+pub struct Client {
+    base_url: String,
+    access_token: Option<String>,
+    agent: ureq::Agent,
+}
+
+impl Client {
+    /// Create a client for the daemon running in this process, using the daemon's address and
+    /// its current first access token. Daemons listening on all interfaces are reached via the
+    /// loopback address. Daemons listening on a Unix domain socket are not supported. When the
+    /// daemon uses TLS, its certificate must be trusted by the root certificates of `ureq`.
+    #[must_use]
+    pub fn new(daemon: &Daemon) -> Self {
+        let mut addr = daemon.local_addr();
+        if addr.ip().is_unspecified() {
+            addr.set_ip(match addr {
+                SocketAddr::V4(_) => Ipv4Addr::LOCALHOST.into(),
+                SocketAddr::V6(_) => Ipv6Addr::LOCALHOST.into(),
+            });
+        }
+        let scheme = if daemon.tls_enabled() {
+            "https"
+        } else {
+            "http"
+        };
+        Client::with_base_url(format!("{scheme}://{addr}"), daemon.access_token())
+    }
+
+    /// Create a client for the daemon listening at `base_url`, e.g. `http://127.0.0.1:3000`.
+    pub fn with_base_url(base_url: impl Into<String>, access_token: Option<String>) -> Self {
+        Client {
+            base_url: base_url.into().trim_end_matches('/').to_string(),
+            access_token,
+            agent: ureq::Agent::new(),
+        }
+    }
+
+    /// Start building a request retrieving the content identified by `cid`.
+    pub fn request(&self, cid: Cid) -> RequestBuilder<'_> {
+        RequestBuilder {
+            client: self,
+            cid,
+            path: None,
+            dag_scope: None,
+            entity_bytes: None,
+            dups: None,
+            car_order: None,
+            filename: None,
+            protocols: Vec::new(),
+            providers: Vec::new(),
+        }
+    }
+}
+
+/// A request for content built by [`Client::request`].
+///
+/// Parameters that are not set are not sent to the daemon, Lassie uses its defaults.
+#[derive(Debug, Clone)]
+#[must_use]
+pub struct RequestBuilder<'a> {
+    client: &'a Client,
+    cid: Cid,
+    path: Option<String>,
+    dag_scope: Option<DagScope>,
+    entity_bytes: Option<ByteRange>,
+    dups: Option<bool>,
+    car_order: Option<CarOrder>,
+    filename: Option<String>,
+    protocols: Vec<Protocol>,
+    providers: Vec<String>,
+}
+
+impl RequestBuilder<'_> {
+    /// Path within the DAG to retrieve, e.g. `some/file.txt`.
+    pub fn path(mut self, path: impl Into<String>) -> Self {
+        self.path = Some(path.into());
+        self
+    }
+
+    /// Which blocks of the DAG to retrieve (`dag-scope`).
+    pub fn dag_scope(mut self, dag_scope: DagScope) -> Self {
+        self.dag_scope = Some(dag_scope);
+        self
+    }
+
+    /// Retrieve only the given byte range of the entity at the end of the path (`entity-bytes`).
+    pub fn entity_bytes(mut self, range: ByteRange) -> Self {
+        self.entity_bytes = Some(range);
+        self
+    }
+
+    /// Whether the CAR stream should include duplicate blocks (`dups`).
+    pub fn dups(mut self, dups: bool) -> Self {
+        self.dups = Some(dups);
+        self
+    }
+
+    /// The order of blocks in the CAR stream (`order`).
+    pub fn car_order(mut self, order: CarOrder) -> Self {
+        self.car_order = Some(order);
+        self
+    }
+
+    /// The file name the daemon should use in the `Content-Disposition` header.
+    pub fn filename(mut self, filename: impl Into<String>) -> Self {
+        self.filename = Some(filename.into());
+        self
+    }
+
+    /// Retrieval protocols allowed for this request.
+    pub fn protocols(mut self, protocols: impl IntoIterator<Item = Protocol>) -> Self {
+        self.protocols = protocols.into_iter().collect();
+        self
+    }
+
+    /// Retrieve the content from these providers only. Each provider is a multiaddr including the
+    /// peer ID.
+    pub fn providers<S: Into<String>>(mut self, providers: impl IntoIterator<Item = S>) -> Self {
+        self.providers = providers.into_iter().map(Into::into).collect();
+        self
+    }
+
+    /// The URL of this request.
+    #[must_use]
+    pub fn url(&self) -> String {
+        let mut url = format!("{}/ipfs/{}", self.client.base_url, self.cid);
+        if let Some(path) = &self.path {
+            for segment in path.split('/').filter(|s| !s.is_empty()) {
+                url.push('/');
+                url.push_str(&percent_encode(segment));
+            }
+        }
+
+        let mut query = Vec::new();
+        if let Some(scope) = self.dag_scope {
+            query.push(("dag-scope", scope.as_str().to_string()));
+        }
+        if let Some(ByteRange { from, to }) = self.entity_bytes {
+            let to = to.map_or_else(|| "*".to_string(), |to| to.to_string());
+            query.push(("entity-bytes", format!("{from}:{to}")));
+        }
+        if let Some(filename) = &self.filename {
+            query.push(("filename", filename.clone()));
+        }
+        if !self.protocols.is_empty() {
+            let names = self
+                .protocols
+                .iter()
+                .map(|p| p.as_str())
+                .collect::<Vec<_>>();
+            query.push(("protocols", names.join(",")));
+        }
+        if !self.providers.is_empty() {
+            query.push(("providers", self.providers.join(",")));
+        }
+
+        for (ix, (name, value)) in query.iter().enumerate() {
+            url.push(if ix == 0 { '?' } else { '&' });
+            url.push_str(name);
+            url.push('=');
+            url.push_str(&percent_encode(value));
+        }
+        url
+    }
+
+    /// The value of the `Accept` header of this request.
+    #[must_use]
+    pub fn accept(&self) -> String {
+        let mut accept = "application/vnd.ipld.car;version=1".to_string();
+        if let Some(order) = self.car_order {
+            accept.push_str(";order=");
+            accept.push_str(order.as_str());
+        }
+        if let Some(dups) = self.dups {
+            accept.push_str(if dups { ";dups=y" } else { ";dups=n" });
+        }
+        accept
+    }
+
+    /// Send the request and return the response once the daemon sent the response headers.
+    ///
+    /// # Errors
+    ///
+    /// This function returns `Err` when the request cannot be sent or when the daemon responds
+    /// with a non-success status code.
+    pub fn send(self) -> Result<Response, ClientError> {
+        let mut request = self
+            .client
+            .agent
+            .get(&self.url())
+            .set("Accept", &self.accept());
+        if let Some(token) = &self.client.access_token {
+            request = request.set("Authorization", &format!("Bearer {token}"));
+        }
+
+        match request.call() {
+            Ok(response) => Ok(Response::from_ureq(response)),
+            Err(ureq::Error::Status(code, response)) => Err(ClientError::Status {
+                code,
+                message: response.into_string().unwrap_or_default(),
+            }),
+            Err(err) => Err(ClientError::Transport(err.to_string())),
+        }
+    }
+}
+
+/// The response of the daemon. Reading the response yields the CAR bytes as they arrive.
+pub struct Response {
+    headers: ResponseHeaders,
+    body: Box<dyn Read + Send + Sync + 'static>,
+}
+
+impl Response {
+    fn from_ureq(response: ureq::Response) -> Self {
+        let raw = response
+            .headers_names()
+            .into_iter()
+            .filter_map(|name| {
+                let value = response.header(&name)?.to_string();
+                Some((name, value))
+            })
+            .collect();
+        Response {
+            headers: ResponseHeaders::parse(raw),
+            body: response.into_reader(),
+        }
+    }
+
+    /// The response headers describing the CAR stream.
+    #[must_use]
+    pub fn headers(&self) -> &ResponseHeaders {
+        &self.headers
+    }
+}
+
+impl Read for Response {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        self.body.read(buf)
+    }
+}
+
+/// Response headers sent by the daemon.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ResponseHeaders {
+    /// The `Content-Type` header, `None` when the response is not a CAR stream.
+    pub content_type: Option<CarContentType>,
+    /// The `Etag` header.
+    pub etag: Option<String>,
+    /// The `X-Ipfs-Path` header, the path of the retrieved content.
+    pub ipfs_path: Option<String>,
+    /// The `X-Trace-Id` header identifying the retrieval in Lassie logs.
+    pub trace_id: Option<String>,
+    /// The file name from the `Content-Disposition` header.
+    pub filename: Option<String>,
+    /// All response headers as received, with names in lower case.
+    pub raw: Vec<(String, String)>,
+}
+
+/// Parameters of the `application/vnd.ipld.car` content type.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CarContentType {
+    pub version: Option<u32>,
+    pub order: Option<CarOrder>,
+    pub dups: Option<bool>,
+}
+
+impl ResponseHeaders {
+    fn parse(raw: Vec<(String, String)>) -> Self {
+        let raw = raw
+            .into_iter()
+            .map(|(name, value)| (name.to_ascii_lowercase(), value))
+            .collect::<Vec<_>>();
+        let get = |name: &str| raw.iter().find(|(n, _)| n == name).map(|(_, v)| v.clone());
+
+        ResponseHeaders {
+            content_type: get("content-type").and_then(|v| CarContentType::parse(&v)),
+            etag: get("etag"),
+            ipfs_path: get("x-ipfs-path"),
+            trace_id: get("x-trace-id"),
+            filename: get("content-disposition").and_then(|v| parse_filename(&v)),
+            raw,
+        }
+    }
+}
+
+impl CarContentType {
+    fn parse(value: &str) -> Option<Self> {
+        let mut parts = value.split(';').map(str::trim);
+        if !parts
+            .next()?
+            .eq_ignore_ascii_case("application/vnd.ipld.car")
+        {
+            return None;
+        }
+
+        let mut result = CarContentType::default();
+        for (name, value) in parts.filter_map(|p| p.split_once('=')) {
+            match name {
+                "version" => result.version = value.parse().ok(),
+                "order" => {
+                    result.order = match value {
+                        "dfs" => Some(CarOrder::Dfs),
+                        "unk" => Some(CarOrder::Unknown),
+                        _ => None,
+                    }
+                }
+                "dups" => {
+                    result.dups = match value {
+                        "y" => Some(true),
+                        "n" => Some(false),
+                        _ => None,
+                    }
+                }
+                _ => {}
+            }
+        }
+        Some(result)
+    }
+}
+
+fn parse_filename(content_disposition: &str) -> Option<String> {
+    content_disposition
+        .split(';')
+        .filter_map(|p| p.trim().split_once('='))
+        .find(|(name, _)| *name == "filename")
+        .map(|(_, value)| value.trim_matches('"').to_string())
+}
+
+/// Percent-encode everything except unreserved characters, see RFC 3986 section 2.3.
+fn percent_encode(value: &str) -> String {
+    let mut result = String::with_capacity(value.len());
+    for byte in value.bytes() {
+        if byte.is_ascii_alphanumeric() || matches!(byte, b'-' | b'.' | b'_' | b'~') {
+            result.push(char::from(byte));
+        } else {
+            // Writing to a String cannot fail
+            let _ = write!(result, "%{byte:02X}");
+        }
+    }
+    result
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    const CID: &str = "bafybeib36krhffuh3cupjml4re2wfxldredkir5wti3dttulyemre7xkni";
+
+    #[test]
+    fn builds_url_with_all_parameters() {
+        let client = Client::with_base_url("http://127.0.0.1:3000/", None);
+        let request = client
+            .request(CID.parse().unwrap())
+            .path("some dir/file.txt")
+            .dag_scope(DagScope::Entity)
+            .entity_bytes(ByteRange { from: 10, to: None })
+            .filename("file.car")
+            .protocols([Protocol::Bitswap, Protocol::Http])
+            .providers([
+                "/ip4/127.0.0.1/tcp/3000/p2p/12D3KooWHgbQZvPjSx2DURHBkvBJWvHWP4ChoNHBzVNG3Lk2Jzfi",
+            ]);
+
+        assert_eq!(
+            request.url(),
+            format!(
+                "http://127.0.0.1:3000/ipfs/{CID}/some%20dir/file.txt\
+                 ?dag-scope=entity\
+                 &entity-bytes=10%3A%2A\
+                 &filename=file.car\
+                 &protocols=bitswap%2Chttp\
+                 &providers=%2Fip4%2F127.0.0.1%2Ftcp%2F3000%2Fp2p%2F12D3KooWHgbQZvPjSx2DURHBkvBJWvHWP4ChoNHBzVNG3Lk2Jzfi"
+            )
+        );
+    }
+
+    #[test]
+    fn builds_accept_header() {
+        let client = Client::with_base_url("http://127.0.0.1:3000", None);
+        let request = client.request(CID.parse().unwrap());
+        assert_eq!(request.accept(), "application/vnd.ipld.car;version=1");
+
+        let request = request.car_order(CarOrder::Dfs).dups(false);
+        assert_eq!(
+            request.accept(),
+            "application/vnd.ipld.car;version=1;order=dfs;dups=n"
+        );
+    }
+
+    #[test]
+    fn parses_response_headers() {
+        let headers = ResponseHeaders::parse(vec![
+            (
+                "Content-Type".to_string(),
+                "application/vnd.ipld.car;version=1;order=dfs;dups=y".to_string(),
+            ),
+            (
+                "Content-Disposition".to_string(),
+                "attachment; filename=\"file.car\"".to_string(),
+            ),
+            ("X-Trace-Id".to_string(), "abc".to_string()),
+        ]);
+
+        assert_eq!(
+            headers.content_type,
+            Some(CarContentType {
+                version: Some(1),
+                order: Some(CarOrder::Dfs),
+                dups: Some(true),
+            })
+        );
+        assert_eq!(headers.filename.as_deref(), Some("file.car"));
+        assert_eq!(headers.trace_id.as_deref(), Some("abc"));
+        assert_eq!(headers.etag, None);
+    }
+}
@@ -0,0 +1,24 @@
+use std::fmt::{Display, Formatter};
+
+#[derive(Debug, PartialEq, Clone)]
+#[non_exhaustive]
+pub enum ClientError {
+    /// The daemon responded with a non-success status code.
+    Status { code: u16, message: String },
+    /// The request could not be sent or the response could not be received.
+    Transport(String),
+}
+
+impl Display for ClientError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+        write!(f, "failed to retrieve content from Lassie daemon: ")?;
+        match self {
+            ClientError::Status { code, message } => {
+                f.write_fmt(format_args!("HTTP status {code}: {message}"))
+            }
+            ClientError::Transport(msg) => f.write_str(msg),
+        }
+    }
+}
+
+impl std::error::Error for ClientError {}
@@ -0,0 +1,242 @@
+use std::collections::BTreeMap;
+use std::ffi::CStr;
+use std::os::raw::c_char;
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::sync::{Mutex, MutexGuard, Once, PoisonError};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use cid::Cid;
+
+use crate::{from_c_string, Protocol};
+
+/// An event reported by Lassie while retrieving content, see
+/// [`Daemon::subscribe_events`](crate::Daemon::subscribe_events).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RetrievalEvent {
+    /// Identifies the retrieval. All events of the same retrieval share the same ID.
+    pub retrieval_id: String,
+
+    /// When the event happened.
+    pub time: SystemTime,
+
+    /// What happened.
+    pub kind: RetrievalEventKind,
+}
+
+/// The different kinds of [`RetrievalEvent`].
+///
+/// `provider` is the peer ID of the storage provider, `protocol` is `None` when Lassie used a
+/// protocol this crate does not know about.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum RetrievalEventKind {
+    /// The retrieval of `root` started.
+    Started { root: Cid },
+
+    /// The indexer returned `count` candidate providers.
+    CandidatesFound { count: u64 },
+
+    /// `count` candidates remained after filtering out providers that cannot serve the request.
+    CandidatesFiltered { count: u64 },
+
+    /// Lassie started retrieving from the provider.
+    ProviderStarted {
+        provider: String,
+        protocol: Option<Protocol>,
+    },
+
+    /// Lassie connected to the provider.
+    ProviderConnected {
+        provider: String,
+        protocol: Option<Protocol>,
+    },
+
+    /// The provider sent the first byte, `duration` is the time it took since the retrieval from
+    /// this provider started.
+    FirstByte {
+        provider: String,
+        protocol: Option<Protocol>,
+        duration: Duration,
+    },
+
+    /// The retrieval from the provider failed. Lassie may still succeed with other providers.
+    ProviderFailed {
+        provider: String,
+        protocol: Option<Protocol>,
+        error: String,
+    },
+
+    /// The content was retrieved from the provider.
+    Succeeded {
+        provider: String,
+        protocol: Option<Protocol>,
+        received_bytes: u64,
+        received_blocks: u64,
+        duration: Duration,
+    },
+
+    /// The retrieval failed.
+    Failed { error: String },
+
+    /// The retrieval finished, either successfully or not. This is the last event of a retrieval.
+    Finished,
+}
+
+#[repr(C)]
+pub(crate) struct GoRetrievalEvent {
+    // this must be kept in sync with the definition of retrieval_event_t in go-lib/lassie-ffi.go
+    kind: *const c_char,
+    retrieval_id: *const c_char,
+    time: i64,
+    root_cid: *const c_char,
+    provider_id: *const c_char,
+    protocol: *const c_char,
+    error: *const c_char,
+    count: u64,
+    received_bytes: u64,
+    received_blocks: u64,
+    duration: i64,
+}
+
+pub(crate) type GoEventFn = extern "C" fn(handle: u64, event: *const GoRetrievalEvent);
+
+/// Channels of event subscribers, keyed by the handle of the daemon.
+static SUBSCRIBERS: Mutex<BTreeMap<u64, Vec<Sender<RetrievalEvent>>>> = Mutex::new(BTreeMap::new());
+
+fn lock_subscribers() -> MutexGuard<'static, BTreeMap<u64, Vec<Sender<RetrievalEvent>>>> {
+    // The map is always in a consistent state, we can ignore poisoning
+    SUBSCRIBERS.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+/// Create a new channel receiving events of the daemon identified by `handle`.
+pub(crate) fn subscribe(handle: u64) -> Receiver<RetrievalEvent> {
+    static REGISTER: Once = Once::new();
+    REGISTER.call_once(|| {
+        // SAFETY:
+        // It's safe to call this FFI function as it does not have any special safety requirements.
+        // `event_from_go` is a plain function that stays valid for the lifetime of the process.
+        unsafe { crate::SetEventCallback(event_from_go) };
+    });
+
+    let (sender, receiver) = channel();
+    lock_subscribers().entry(handle).or_default().push(sender);
+    receiver
+}
+
+/// Drop the channels of all subscribers of the daemon identified by `handle`, which ends their
+/// streams of events.
+pub(crate) fn unsubscribe_all(handle: u64) {
+    lock_subscribers().remove(&handle);
+}
+
+extern "C" fn event_from_go(handle: u64, event: *const GoRetrievalEvent) {
+    let mut subscribers = lock_subscribers();
+    let Some(senders) = subscribers.get_mut(&handle) else {
+        return;
+    };
+
+    // SAFETY:
+    // Go passes a pointer to a valid event that lives until this function returns.
+    let Some(event) = RetrievalEvent::from_go(unsafe { &*event }) else {
+        return;
+    };
+
+    // Remove subscribers that dropped their receivers
+    senders.retain(|sender| sender.send(event.clone()).is_ok());
+}
+
+impl RetrievalEvent {
+    fn from_go(event: &GoRetrievalEvent) -> Option<Self> {
+        let time = UNIX_EPOCH + Duration::from_nanos(u64::try_from(event.time).unwrap_or(0));
+        Some(RetrievalEvent {
+            retrieval_id: from_c_string(event.retrieval_id).unwrap_or_default(),
+            time,
+            kind: RetrievalEventKind::from_go(event)?,
+        })
+    }
+}
+
+impl RetrievalEventKind {
+    // The kinds must be kept in sync with forwardEvent() in go-lib/lassie-ffi.go
+    fn from_go(event: &GoRetrievalEvent) -> Option<Self> {
+        let string = |value: *const c_char| from_c_string(value).unwrap_or_default();
+        let provider = string(event.provider_id);
+        let protocol = Protocol::from_name(&string(event.protocol));
+        let duration = Duration::from_nanos(u64::try_from(event.duration).unwrap_or(0));
+
+        // SAFETY:
+        // `kind` is always set by Go to a valid C string.
+        let kind = unsafe { CStr::from_ptr(event.kind) }.to_str().ok()?;
+        let kind = match kind {
+            "started" => RetrievalEventKind::Started {
+                root: Cid::try_from(string(event.root_cid)).ok()?,
+            },
+            "candidates-found" => RetrievalEventKind::CandidatesFound { count: event.count },
+            "candidates-filtered" => RetrievalEventKind::CandidatesFiltered { count: event.count },
+            "provider-started" => RetrievalEventKind::ProviderStarted { provider, protocol },
+            "provider-connected" => RetrievalEventKind::ProviderConnected { provider, protocol },
+            "first-byte" => RetrievalEventKind::FirstByte {
+                provider,
+                protocol,
+                duration,
+            },
+            "provider-failed" => RetrievalEventKind::ProviderFailed {
+                provider,
+                protocol,
+                error: string(event.error),
+            },
+            "succeeded" => RetrievalEventKind::Succeeded {
+                provider,
+                protocol,
+                received_bytes: event.received_bytes,
+                received_blocks: event.received_blocks,
+                duration,
+            },
+            "failed" => RetrievalEventKind::Failed {
+                error: string(event.error),
+            },
+            "finished" => RetrievalEventKind::Finished,
+            _ => return None,
+        };
+        Some(kind)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use pretty_assertions::assert_eq;
+    use std::ptr::null;
+
+    #[test]
+    fn converts_go_events() {
+        let go_event = GoRetrievalEvent {
+            kind: c"succeeded".as_ptr(),
+            retrieval_id: c"8f3d".as_ptr(),
+            time: 1_000_000_123,
+            root_cid: c"".as_ptr(),
+            provider_id: c"12D3KooWHgbQZvPjSx2DURHBkvBJWvHWP4ChoNHBzVNG3Lk2Jzfi".as_ptr(),
+            protocol: c"bitswap".as_ptr(),
+            error: null(),
+            count: 0,
+            received_bytes: 1024,
+            received_blocks: 3,
+            duration: 2_000_000,
+        };
+
+        assert_eq!(
+            RetrievalEvent::from_go(&go_event),
+            Some(RetrievalEvent {
+                retrieval_id: "8f3d".to_string(),
+                time: UNIX_EPOCH + Duration::from_nanos(1_000_000_123),
+                kind: RetrievalEventKind::Succeeded {
+                    provider: "12D3KooWHgbQZvPjSx2DURHBkvBJWvHWP4ChoNHBzVNG3Lk2Jzfi".to_string(),
+                    protocol: Some(Protocol::Bitswap),
+                    received_bytes: 1024,
+                    received_blocks: 3,
+                    duration: Duration::from_millis(2),
+                }
+            })
+        );
+    }
+}
@@ -0,0 +1,358 @@
+use std::ffi::{c_int, c_void, CStr, CString};
+use std::io::Read;
+use std::os::raw::c_char;
+use std::sync::mpsc::{sync_channel, Receiver, SyncSender};
+
+use cid::Cid;
+
+use crate::car::{CarError, CarReader};
+use crate::{to_c_providers, FetchError, GoProvider, LassieResult, Protocol, Provider};
+
+/// How many chunks of CAR data can be buffered before Lassie has to wait for the reader.
+pub(crate) const CHANNEL_CAPACITY: usize = 16;
+
+/// Which blocks of the DAG to retrieve, see
+/// [Trustless Gateway: `dag-scope`](https://specs.ipfs.tech/http-gateways/trustless-gateway/#dag-scope-request-query-parameter).
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum DagScope {
+    /// The entire DAG rooted at the terminal element of the path.
+    #[default]
+    All,
+    /// The blocks required to read the terminal element of the path, e.g. a complete file, or
+    /// a directory listing without the directory entries.
+    Entity,
+    /// Only the block of the terminal element of the path.
+    Block,
+}
+
+impl DagScope {
+    /// The name used for this scope in the `dag-scope` query parameter.
+    #[must_use]
+    pub fn as_str(self) -> &'static str {
+        match self {
+            DagScope::All => "all",
+            DagScope::Entity => "entity",
+            DagScope::Block => "block",
+        }
+    }
+
+    // The names must be kept in sync with parseDagScope() in go-lib/lassie-ffi.go
+    fn as_c_str(self) -> &'static CStr {
+        match self {
+            DagScope::All => c"all",
+            DagScope::Entity => c"entity",
+            DagScope::Block => c"block",
+        }
+    }
+}
+
+/// A byte range of the entity to retrieve, see
+/// [Trustless Gateway: `entity-bytes`](https://specs.ipfs.tech/http-gateways/trustless-gateway/#entity-bytes-request-query-parameter).
+///
+/// Negative values are offsets counted from the end of the entity.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ByteRange {
+    pub from: i64,
+    /// The last byte to retrieve (inclusive). `None` means the end of the entity.
+    pub to: Option<i64>,
+}
+
+/// Options controlling a retrieval made via [`Daemon::fetch`](crate::Daemon::fetch).
+#[derive(Debug, Clone, Default)]
+pub struct FetchOptions {
+    /// Path within the DAG to retrieve, e.g. `some/file.txt`.
+    pub path: Option<String>,
+
+    /// Which blocks of the DAG to retrieve. Defaults to the entire DAG.
+    pub dag_scope: DagScope,
+
+    /// Retrieve only the given byte range of the entity at the end of the path.
+    pub entity_bytes: Option<ByteRange>,
+
+    /// Include duplicate blocks in the CAR stream, so that the stream can be consumed in a
+    /// streaming fashion without keeping already seen blocks in memory.
+    pub duplicates: bool,
+
+    /// The maximum number of blocks to fetch. Defaults to `DaemonConfig::max_blocks`.
+    pub max_blocks: Option<u64>,
+
+    /// Retrieve the content from these providers only. Defaults to the providers discovered by
+    /// the daemon.
+    pub providers: Vec<Provider>,
+
+    /// Retrieval protocols allowed for this retrieval. Defaults to the protocols configured for
+    /// the daemon.
+    pub protocols: Vec<Protocol>,
+}
+
+#[repr(C)]
+pub(crate) struct GoFetchRequest {
+    // this must be kept in sync with the definition of fetch_request_t in go-lib/lassie-ffi.go
+    root: *const c_char,
+    path: *const c_char,
+    dag_scope: *const c_char,
+    has_entity_bytes: bool,
+    entity_bytes_from: i64,
+    has_entity_bytes_to: bool,
+    entity_bytes_to: i64,
+    duplicates: bool,
+    max_blocks: u64,
+    providers: *const GoProvider,
+    providers_len: usize,
+    protocols: *const *const c_char,
+    protocols_len: usize,
+    fetch_id: u64,
+}
+
+pub(crate) type FetchWriteFn =
+    extern "C" fn(ctx: *mut c_void, data: *const u8, len: usize) -> c_int;
+
+/// Create the Go context of a retrieval that can be aborted by [`cancel_fetch`].
+pub(crate) fn create_fetch() -> u64 {
+    // SAFETY: CreateFetch does not take any arguments
+    unsafe { crate::CreateFetch() }
+}
+
+/// Abort the retrieval created by [`create_fetch`]. Does nothing when it has already finished.
+pub(crate) fn cancel_fetch(fetch_id: u64) {
+    // SAFETY: CancelFetch ignores ids of retrievals that are not running
+    unsafe { crate::CancelFetch(fetch_id) }
+}
+
+/// Run the retrieval on the current thread using the daemon identified by `handle`, passing all
+/// received CAR bytes to `sink`. The sink returns `false` to abort the retrieval.
+///
+/// `fetch_id` is the id returned by [`create_fetch`], or 0 when the retrieval cannot be cancelled.
+pub(crate) fn run_fetch(
+    handle: u64,
+    fetch_id: u64,
+    cid: &Cid,
+    options: &FetchOptions,
+    sink: &mut dyn FnMut(&[u8]) -> bool,
+) -> Result<(), FetchError> {
+    // It's safe to call unwrap() here, the string representation of a CID never contains null bytes
+    let root = CString::new(cid.to_string()).unwrap();
+
+    let path = options.path.clone().unwrap_or_default();
+    let path = CString::new(path.clone()).map_err(|_| FetchError::PathContainsNullByte(path))?;
+
+    let providers =
+        to_c_providers(&options.providers).map_err(FetchError::ProviderContainsNullByte)?;
+    let go_providers = GoProvider::from_c_providers(&providers);
+
+    let go_protocols = options
+        .protocols
+        .iter()
+        .map(|p| p.as_c_str().as_ptr())
+        .collect::<Vec<_>>();
+
+    let request = GoFetchRequest {
+        root: root.as_ptr(),
+        path: path.as_ptr(),
+        dag_scope: options.dag_scope.as_c_str().as_ptr(),
+        has_entity_bytes: options.entity_bytes.is_some(),
+        entity_bytes_from: options.entity_bytes.map_or(0, |r| r.from),
+        has_entity_bytes_to: options.entity_bytes.and_then(|r| r.to).is_some(),
+        entity_bytes_to: options.entity_bytes.and_then(|r| r.to).unwrap_or(0),
+        duplicates: options.duplicates,
+        max_blocks: options.max_blocks.unwrap_or(0),
+        providers: go_providers.as_ptr(),
+        providers_len: go_providers.len(),
+        protocols: go_protocols.as_ptr(),
+        protocols_len: go_protocols.len(),
+        fetch_id,
+    };
+
+    let mut sink = sink;
+    log::debug!("Fetching {cid} via Lassie.Fetch");
+    // SAFETY:
+    // All strings and arrays referenced by `request` live until the end of this function.
+    // `write_to_sink` is called only while Fetch is running, and it interprets `ctx` as the
+    // pointer to `sink` we are passing here.
+    let result: LassieResult = unsafe {
+        crate::Fetch(
+            handle,
+            std::ptr::addr_of!(request),
+            write_to_sink,
+            std::ptr::addr_of_mut!(sink).cast::<c_void>(),
+        )
+    };
+
+    match result.error() {
+        None => Ok(()),
+        Some(msg) => {
+            log::debug!("Lassie.Fetch failed: {msg}");
+            Err(FetchError::from_go(result.error_kind, msg))
+        }
+    }
+}
+
+/// Retrieve the single block identified by `cid` and verify its data against the CID.
+pub(crate) fn get_block(handle: u64, cid: &Cid) -> Result<Vec<u8>, FetchError> {
+    // Lassie does not support the `application/vnd.ipld.raw` response format. We request a CAR
+    // stream containing only the root block instead.
+    let options = FetchOptions {
+        dag_scope: DagScope::Block,
+        ..FetchOptions::default()
+    };
+    let mut car = Vec::new();
+    run_fetch(handle, 0, cid, &options, &mut |data| {
+        car.extend_from_slice(data);
+        true
+    })?;
+    block_from_car(&car, cid)
+}
+
+fn block_from_car(car: &[u8], cid: &Cid) -> Result<Vec<u8>, FetchError> {
+    let to_fetch_error = |err| match err {
+        CarError::HashMismatch(cid) => FetchError::HashMismatch(cid),
+        err => FetchError::InvalidResponse(err.to_string()),
+    };
+    for block in CarReader::new(car).map_err(to_fetch_error)? {
+        let (block_cid, data) = block.map_err(to_fetch_error)?;
+        if block_cid == *cid {
+            return Ok(data);
+        }
+    }
+    Err(FetchError::NotFound(format!(
+        "the response does not include block {cid}"
+    )))
+}
+
+extern "C" fn write_to_sink(ctx: *mut c_void, data: *const u8, len: usize) -> c_int {
+    // SAFETY:
+    // `ctx` is the pointer to the `&mut dyn FnMut` sink created by `run_fetch`, which is blocked
+    // in the FFI call until the retrieval finishes. Go guarantees that `data` points to `len`
+    // readable bytes for the duration of this call.
+    let (sink, data) = unsafe {
+        (
+            &mut *ctx.cast::<&mut dyn FnMut(&[u8]) -> bool>(),
+            std::slice::from_raw_parts(data, len),
+        )
+    };
+    c_int::from(!sink(data))
+}
+
+pub(crate) enum Message {
+    Data(Vec<u8>),
+    Done(Result<(), FetchError>),
+}
+
+/// A stream of CAR bytes produced by [`Daemon::fetch`](crate::Daemon::fetch).
+///
+/// When the retrieval fails, the stream reports the [`FetchError`] wrapped in `std::io::Error`
+/// of kind [`std::io::ErrorKind::Other`]. Dropping the stream aborts the retrieval.
+pub struct CarStream {
+    fetch_id: u64,
+    receiver: Receiver<Message>,
+    chunk: Vec<u8>,
+    offset: usize,
+    finished: bool,
+}
+
+impl CarStream {
+    pub(crate) fn start(handle: u64, cid: Cid, options: FetchOptions) -> Self {
+        let fetch_id = create_fetch();
+        let (sender, receiver) = sync_channel(CHANNEL_CAPACITY);
+        std::thread::spawn(move || {
+            let data_sender: SyncSender<Message> = sender.clone();
+            let result = run_fetch(handle, fetch_id, &cid, &options, &mut |data| {
+                data_sender.send(Message::Data(data.to_vec())).is_ok()
+            });
+            // The receiver may have been dropped already, there is nobody to report the result to
+            let _ = sender.send(Message::Done(result));
+        });
+
+        CarStream {
+            fetch_id,
+            receiver,
+            chunk: Vec::new(),
+            offset: 0,
+            finished: false,
+        }
+    }
+}
+
+impl Read for CarStream {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        while self.offset >= self.chunk.len() {
+            if self.finished {
+                return Ok(0);
+            }
+
+            match self.receiver.recv() {
+                Ok(Message::Data(data)) => {
+                    self.chunk = data;
+                    self.offset = 0;
+                }
+                Ok(Message::Done(result)) => {
+                    self.finished = true;
+                    result.map_err(std::io::Error::other)?;
+                }
+                Err(_) => {
+                    self.finished = true;
+                    return Err(std::io::Error::other(FetchError::Lassie(
+                        "the retrieval thread exited unexpectedly".to_string(),
+                    )));
+                }
+            }
+        }
+
+        let len = buf.len().min(self.chunk.len() - self.offset);
+        buf[..len].copy_from_slice(&self.chunk[self.offset..self.offset + len]);
+        self.offset += len;
+        Ok(len)
+    }
+}
+
+impl Drop for CarStream {
+    fn drop(&mut self) {
+        // Lassie may be waiting for providers without producing any data, closing the channel
+        // alone would not stop the retrieval
+        cancel_fetch(self.fetch_id);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    const CAR: &[u8] = include_bytes!(
+        "../tests/testdata/bafkreih25dih6ug3xtj73vswccw423b56ilrwmnos4cbwhrceudopdp5sq.car"
+    );
+
+    fn root() -> Cid {
+        "bafkreih25dih6ug3xtj73vswccw423b56ilrwmnos4cbwhrceudopdp5sq"
+            .parse()
+            .unwrap()
+    }
+
+    #[test]
+    fn extracts_block_from_car() {
+        let data = block_from_car(CAR, &root()).expect("cannot extract the block");
+        assert!(!data.is_empty(), "the block data should not be empty");
+        assert!(CAR.ends_with(&data));
+    }
+
+    #[test]
+    fn reports_hash_mismatch() {
+        let mut car = CAR.to_vec();
+        *car.last_mut().unwrap() ^= 0xff;
+        assert_eq!(
+            block_from_car(&car, &root()),
+            Err(FetchError::HashMismatch(root()))
+        );
+    }
+
+    #[test]
+    fn reports_missing_block() {
+        let other: Cid = "bafkreiaxwvxsbnqxbgfsmxbwzbvrpltcwcjvzofxlkrfh3ymrrd4g2r2xe"
+            .parse()
+            .unwrap();
+        assert!(matches!(
+            block_from_car(CAR, &other),
+            Err(FetchError::NotFound(_))
+        ));
+    }
+}
@@ -0,0 +1,60 @@
+use std::ffi::c_int;
+use std::fmt::{Display, Formatter};
+
+use cid::Cid;
+
+// The error kinds reported by Go, must be kept in sync with errorKind* constants in
+// go-lib/lassie-ffi.go
+const ERROR_KIND_NOT_FOUND: c_int = 1;
+const ERROR_KIND_TIMEOUT: c_int = 2;
+
+#[derive(Debug, PartialEq, Clone)]
+#[non_exhaustive]
+pub enum FetchError {
+    PathContainsNullByte(String),
+    ProviderContainsNullByte(String),
+    Lassie(String),
+    /// No provider has the requested content, or the response did not include it.
+    NotFound(String),
+    /// The retrieval did not finish within the configured timeout.
+    Timeout(String),
+    /// The data received for the block does not match the hash in its CID.
+    HashMismatch(Cid),
+    /// The CAR response could not be parsed.
+    InvalidResponse(String),
+}
+
+impl FetchError {
+    pub(crate) fn from_go(kind: c_int, msg: String) -> Self {
+        match kind {
+            ERROR_KIND_NOT_FOUND => FetchError::NotFound(msg),
+            ERROR_KIND_TIMEOUT => FetchError::Timeout(msg),
+            _ => FetchError::Lassie(msg),
+        }
+    }
+}
+
+impl Display for FetchError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+        write!(f, "failed to fetch content: ")?;
+        match self {
+            FetchError::PathContainsNullByte(path) => f.write_fmt(format_args!(
+                "null bytes are not allowed in paths (value: {path:?})",
+            )),
+            FetchError::ProviderContainsNullByte(addr) => f.write_fmt(format_args!(
+                "null bytes are not allowed in provider addresses (value: {addr:?})",
+            )),
+            FetchError::Lassie(msg) | FetchError::NotFound(msg) | FetchError::Timeout(msg) => {
+                f.write_str(msg)
+            }
+            FetchError::HashMismatch(cid) => f.write_fmt(format_args!(
+                "the data of block {cid} does not match its hash"
+            )),
+            FetchError::InvalidResponse(msg) => {
+                f.write_fmt(format_args!("invalid CAR response: {msg}"))
+            }
+        }
+    }
+}
+
+impl std::error::Error for FetchError {}
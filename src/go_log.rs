@@ -0,0 +1,73 @@
+use std::ffi::CStr;
+use std::os::raw::c_char;
+use std::sync::Once;
+
+pub(crate) type GoLogFn =
+    extern "C" fn(level: usize, target: *const c_char, message: *const c_char);
+
+/// Register the callback forwarding log messages from Go (Lassie and our wrapper) to the `log`
+/// crate. Only the first call registers the callback, subsequent calls are no-ops.
+pub(crate) fn init() {
+    static REGISTER: Once = Once::new();
+    REGISTER.call_once(|| {
+        // SAFETY:
+        // It's safe to call this FFI function as it does not have any special safety requirements.
+        // `log_from_go` is a plain function that stays valid for the lifetime of the process.
+        unsafe { crate::SetLogCallback(log_from_go) };
+    });
+}
+
+// The numbering must be kept in sync with the log levels described in go-lib/lassie-ffi.go
+fn to_log_level(level: usize) -> log::Level {
+    match level {
+        0 | 1 => log::Level::Error,
+        2 => log::Level::Warn,
+        3 => log::Level::Info,
+        4 => log::Level::Debug,
+        _ => log::Level::Trace,
+    }
+}
+
+extern "C" fn log_from_go(level: usize, target: *const c_char, message: *const c_char) {
+    let level = to_log_level(level);
+    if level > log::max_level() {
+        return;
+    }
+
+    // SAFETY:
+    // Go passes NUL-terminated strings that are not NULL and live until this function returns.
+    let (target, message) = unsafe {
+        (
+            CStr::from_ptr(target).to_string_lossy(),
+            CStr::from_ptr(message).to_string_lossy(),
+        )
+    };
+
+    log::logger().log(
+        &log::Record::builder()
+            .args(format_args!("{message}"))
+            .level(level)
+            .target(&target)
+            .build(),
+    );
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn converts_go_levels_to_log_levels() {
+        // The levels passed by Go match the numeric values of `log::LevelFilter`
+        for level in [
+            log::Level::Error,
+            log::Level::Warn,
+            log::Level::Info,
+            log::Level::Debug,
+            log::Level::Trace,
+        ] {
+            assert_eq!(to_log_level(level.to_level_filter() as usize), level);
+        }
+    }
+}
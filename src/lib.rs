@@ -1,12 +1,54 @@
+use std::collections::HashSet;
 use std::ffi::{CStr, CString};
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
 use std::os::raw::c_char;
-use std::path::PathBuf;
-use std::sync::{Mutex, MutexGuard};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, PoisonError};
 use std::time::Duration;
 
+mod access_token;
+mod access_token_error;
+#[cfg(feature = "tokio")]
+mod async_daemon;
+pub mod car;
+mod car_error;
+#[cfg(feature = "client")]
+pub mod client;
+#[cfg(feature = "client")]
+mod client_error;
+mod events;
+mod fetch;
+mod fetch_error;
+mod go_log;
+mod peer_id;
+mod protocol;
+mod provider;
 mod start_error;
+mod status;
+mod stop_error;
+#[cfg(feature = "testing")]
+pub mod testing;
+mod tls_error;
+pub mod unixfs;
+mod unixfs_error;
 
+pub use access_token::{AccessToken, AccessTokenPolicy, AccessTokenUsage};
+pub use access_token_error::AccessTokenError;
+#[cfg(feature = "tokio")]
+pub use async_daemon::AsyncCarStream;
+pub use cid::Cid;
+pub use events::{RetrievalEvent, RetrievalEventKind};
+pub use fetch::{ByteRange, CarStream, DagScope, FetchOptions};
+pub use fetch_error::FetchError;
+pub use protocol::Protocol;
+pub use provider::Provider;
 pub use start_error::StartError;
+pub use status::DaemonStatus;
+pub use stop_error::StopError;
+pub use tls_error::TlsError;
+pub use unixfs_error::UnixfsError;
+
+use status::StatusMonitor;
 
 #[cfg_attr(
     all(target_os = "windows", target_env = "msvc"),
@@ -19,16 +61,47 @@ pub use start_error::StartError;
 extern "C" {
     fn InitDaemon(config: *const GoDaemonConfig) -> InitDaemonResult;
     fn DropDaemonInitResult(result: *mut InitDaemonResult);
-    fn RunDaemon() -> LassieResult;
-    fn StopDaemon() -> LassieResult;
+    fn RunDaemon(handle: u64) -> LassieResult;
+    fn StopDaemon(handle: u64) -> LassieResult;
     fn DropResult(value: *mut LassieResult);
+    fn Fetch(
+        handle: u64,
+        request: *const fetch::GoFetchRequest,
+        write: fetch::FetchWriteFn,
+        write_ctx: *mut std::ffi::c_void,
+    ) -> LassieResult;
+    fn CreateFetch() -> u64;
+    fn CancelFetch(fetch_id: u64);
+    fn SetLogCallback(callback: go_log::GoLogFn);
+    fn SetEventCallback(callback: events::GoEventFn);
+    fn SetAccessTokens(
+        handle: u64,
+        tokens: *const GoAccessToken,
+        tokens_len: usize,
+    ) -> LassieResult;
+    fn GetAccessTokenUsage(
+        handle: u64,
+        token: *const c_char,
+        usage: *mut GoAccessTokenUsage,
+    ) -> LassieResult;
+    fn ReloadTls(
+        handle: u64,
+        cert: *const u8,
+        cert_len: usize,
+        key: *const u8,
+        key_len: usize,
+    ) -> LassieResult;
 }
 
 #[repr(C)]
 #[derive(Debug)]
 struct InitDaemonResult {
+    // this must be kept in sync with the definition of daemon_init_result_t in go-lib/lassie-ffi.go
+    handle: u64,
     port: u16,
+    metrics_port: u16,
     error: *const c_char,
+    error_kind: std::ffi::c_int,
 }
 
 impl Drop for InitDaemonResult {
@@ -51,7 +124,9 @@ impl InitDaemonResult {
 #[repr(C)]
 #[derive(Debug)]
 struct LassieResult {
+    // this must be kept in sync with the definition of result_t in go-lib/lassie-ffi.go
     error: *const c_char,
+    error_kind: std::ffi::c_int,
 }
 
 impl Drop for LassieResult {
@@ -85,38 +160,142 @@ fn from_c_string(str: *const c_char) -> Option<String> {
 struct GoDaemonConfig {
     // this must be kept in sync with the definition of daemon_config_t in go-lib/lassie-ffi.go
     temp_dir: *const c_char,
+    listen_addr: *const c_char,
     port: u16,
+    unix_socket: *const c_char,
+    unix_socket_mode: u32,
     log_level: usize,
     max_blocks: u64,
     provider_timeout: i64,
     global_timeout: i64,
-    access_token: *const c_char,
+    access_tokens: *const GoAccessToken,
+    access_tokens_len: usize,
     lassie_user_agent: *const c_char,
+    providers: *const GoProvider,
+    providers_len: usize,
+    indexer_url: *const c_char,
+    protocols: *const *const c_char,
+    protocols_len: usize,
+    libp2p_low_water: u32,
+    libp2p_high_water: u32,
+    concurrent_sp_retrievals: u32,
+    bitswap_concurrency: u32,
+    excluded_providers: *const *const c_char,
+    excluded_providers_len: usize,
+    metrics_enabled: bool,
+    metrics_port: u16,
+    cache_dir: *const c_char,
+    cache_max_size: u64,
+    tls_enabled: bool,
+    tls_cert: *const u8,
+    tls_cert_len: usize,
+    tls_key: *const u8,
+    tls_key_len: usize,
 }
 
-struct GoDaemon {
-    handler_thread: std::thread::JoinHandle<()>,
+#[repr(C)]
+struct GoProvider {
+    // this must be kept in sync with the definition of provider_t in go-lib/lassie-ffi.go
+    addr: *const c_char,
+    protocol: *const c_char,
 }
 
-static mut DAEMON: Mutex<Option<GoDaemon>> = Mutex::new(None);
-fn get_global_daemon() -> std::sync::LockResult<MutexGuard<'static, Option<GoDaemon>>> {
-    // SAFETY:
-    // We are accessing the global variable from this place only and it's protected by a Mutex.
-    unsafe { DAEMON.lock() }
+impl GoProvider {
+    fn from_c_providers(providers: &[(CString, &'static CStr)]) -> Vec<GoProvider> {
+        providers
+            .iter()
+            .map(|(addr, protocol)| GoProvider {
+                addr: addr.as_ptr(),
+                protocol: protocol.as_ptr(),
+            })
+            .collect()
+    }
+}
+
+#[repr(C)]
+struct GoAccessToken {
+    // this must be kept in sync with the definition of access_token_t in go-lib/lassie-ffi.go
+    token: *const c_char,
+    max_concurrent_retrievals: u32,
+    requests_per_minute: u32,
+    bytes_per_day: u64,
+    max_blocks: u64,
+}
+
+impl GoAccessToken {
+    fn from_c_access_tokens(tokens: &[(CString, AccessTokenPolicy)]) -> Vec<GoAccessToken> {
+        tokens
+            .iter()
+            .map(|(token, policy)| GoAccessToken {
+                token: token.as_ptr(),
+                max_concurrent_retrievals: policy.max_concurrent_retrievals.unwrap_or(0),
+                requests_per_minute: policy.requests_per_minute.unwrap_or(0),
+                bytes_per_day: policy.bytes_per_day.unwrap_or(0),
+                max_blocks: policy.max_blocks.unwrap_or(0),
+            })
+            .collect()
+    }
+}
+
+#[repr(C)]
+#[derive(Default)]
+struct GoAccessTokenUsage {
+    // this must be kept in sync with the definition of access_token_usage_t in go-lib/lassie-ffi.go
+    found: bool,
+    active_retrievals: u32,
+    requests_last_minute: u32,
+    bytes_today: u64,
+}
+
+/// An array of C strings that can be passed to Go as `const char**` and length.
+struct CStringArray {
+    // The pointers in `ptrs` point to the memory owned by `values`
+    _values: Vec<CString>,
+    ptrs: Vec<*const c_char>,
 }
 
-#[derive(Debug, Clone, Default)]
+impl CStringArray {
+    fn new(values: Vec<CString>) -> Self {
+        let ptrs = values.iter().map(|v| v.as_ptr()).collect();
+        CStringArray {
+            _values: values,
+            ptrs,
+        }
+    }
+
+    fn as_ptr(&self) -> *const *const c_char {
+        self.ptrs.as_ptr()
+    }
+
+    fn len(&self) -> usize {
+        self.ptrs.len()
+    }
+}
+
+#[derive(Debug, Clone)]
 pub struct DaemonConfig {
     /// Directory where to store temporary files (CAR store).
     ///
     /// By default, Lassie stores temporary files in the OS-specific temp directory.
     pub temp_dir: Option<PathBuf>,
 
+    /// IP address where to listen, e.g. `::1` or `0.0.0.0` for all interfaces.
+    ///
+    /// By default, the daemon listens on `127.0.0.1` only. Listening on other than loopback
+    /// addresses requires `access_tokens`.
+    pub listen_addr: IpAddr,
+
     /// Port where to listen.
     ///
     /// By default, we ask the operating system to choose a free ephemeral port.
     pub port: u16,
 
+    /// Listen on a Unix domain socket instead of a TCP port. `listen_addr` and `port` are
+    /// ignored when this option is set.
+    ///
+    /// This option is supported on Unix platforms only.
+    pub unix_socket: Option<UnixSocketConfig>,
+
     /// MaxBlocks optionally specifies the maximum number of blocks to fetch.
     ///
     /// When the requested CID contains more blocks than specified, the HTTP response will be
@@ -144,147 +323,627 @@ pub struct DaemonConfig {
 
     /// Require retrieval requests to provide authorization header with the configured access token.
     ///
-    /// For example: `Authorization: Bearer {token}`
+    /// The token is accepted in addition to `access_tokens`, without any limits.
+    #[deprecated(note = "use `access_tokens` instead")]
     pub access_token: Option<String>,
+
+    /// Require retrieval requests to provide authorization header with one of the configured
+    /// access tokens. Use [`Daemon::set_access_tokens`] to rotate the tokens at runtime.
+    ///
+    /// For example: `Authorization: Bearer {token}`
+    ///
+    /// Each token can limit the requests it authorizes, see [`AccessTokenPolicy`].
+    pub access_tokens: Vec<AccessToken>,
+
+    /// Serve the HTTP API over TLS using this certificate and private key, see
+    /// [`Daemon::reload_tls`] for replacing the certificate while the daemon is running.
+    ///
+    /// TLS is disabled by default.
+    pub tls: Option<TlsConfig>,
+
+    /// Retrieve content only from these providers instead of asking the network indexer
+    /// (`cid.contact`) for candidates.
+    ///
+    /// By default, Lassie discovers providers using the indexer.
+    pub providers: Vec<Provider>,
+
+    /// URL of the IPNI indexer endpoint to use for finding retrieval candidates, e.g.
+    /// `http://127.0.0.1:3000`.
+    ///
+    /// By default, Lassie uses `https://cid.contact`. This option is ignored when `providers`
+    /// are configured.
+    pub indexer_url: Option<String>,
+
+    /// Retrieval protocols Lassie is allowed to use.
+    ///
+    /// By default, Lassie uses all protocols it supports.
+    pub protocols: Option<HashSet<Protocol>>,
+
+    /// Limit the number of connections kept open by the libp2p host.
+    ///
+    /// By default, Lassie uses the libp2p defaults.
+    pub libp2p_conn_manager: Option<ConnManagerConfig>,
+
+    /// Maximum number of storage providers to retrieve from concurrently (per retrieval).
+    ///
+    /// The default limit is controlled by Go version of Lassie.
+    pub concurrent_sp_retrievals: Option<u32>,
+
+    /// Number of concurrent Bitswap workers.
+    ///
+    /// The default limit is controlled by Go version of Lassie.
+    pub bitswap_concurrency: Option<u32>,
+
+    /// Peer IDs of storage providers that Lassie must never retrieve from, e.g.
+    /// `12D3KooWHgbQZvPjSx2DURHBkvBJWvHWP4ChoNHBzVNG3Lk2Jzfi`.
+    pub excluded_providers: Vec<String>,
+
+    /// Serve Prometheus metrics at `/metrics` on a separate port of
+    /// [`listen_addr`](Self::listen_addr).
+    ///
+    /// Metrics are disabled by default.
+    pub metrics: Option<MetricsConfig>,
+
+    /// Keep retrieved blocks in a persistent cache and serve retrievals from the cache when it
+    /// has all blocks requested.
+    ///
+    /// The cache is disabled by default.
+    pub cache: Option<CacheConfig>,
 }
 
+impl Default for DaemonConfig {
+    #[allow(deprecated)]
+    fn default() -> Self {
+        DaemonConfig {
+            temp_dir: None,
+            listen_addr: IpAddr::V4(Ipv4Addr::LOCALHOST),
+            port: 0,
+            unix_socket: None,
+            max_blocks: None,
+            provider_timeout: None,
+            global_timeout: None,
+            access_token: None,
+            access_tokens: Vec::new(),
+            tls: None,
+            providers: Vec::new(),
+            indexer_url: None,
+            protocols: None,
+            libp2p_conn_manager: None,
+            concurrent_sp_retrievals: None,
+            bitswap_concurrency: None,
+            excluded_providers: Vec::new(),
+            metrics: None,
+            cache: None,
+        }
+    }
+}
+
+/// Watermarks of the libp2p connection manager.
+///
+/// When the number of open connections exceeds `high_water`, the connection manager closes
+/// connections until there are only `low_water` connections left.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ConnManagerConfig {
+    pub low_water: u32,
+    pub high_water: u32,
+}
+
+/// Configuration of the Prometheus metrics endpoint.
+///
+/// The metrics are collected from retrieval events: retrievals started, active and failed,
+/// retrievals from storage providers per protocol and result, bytes received per protocol and
+/// time to first byte per protocol. The daemon also counts `/ipfs/` requests per status code,
+/// response bytes written to HTTP clients, in-process fetches and the bytes they returned, and
+/// retrievals served from the block cache.
+///
+/// The metrics server listens on [`DaemonConfig::listen_addr`], which is loopback by default.
+/// It does not check access tokens.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct MetricsConfig {
+    /// Port where to serve the metrics.
+    ///
+    /// By default, we ask the operating system to choose a free ephemeral port.
+    pub port: u16,
+}
+
+/// Configuration of the persistent block cache.
+///
+/// The cache stores one file per block in `path`. When the total size of the cached blocks exceeds
+/// `max_size`, the least recently used blocks are removed. The cache survives restarts of the
+/// daemon, but it must not be shared by daemons running at the same time.
+///
+/// A retrieval is served from the cache only when the cache has all blocks the retrieval needs,
+/// otherwise all blocks are retrieved from providers and stored in the cache.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CacheConfig {
+    /// Directory where to store the cached blocks. It's created when it does not exist.
+    pub path: PathBuf,
+
+    /// Maximum total size of the cached blocks in bytes.
+    pub max_size: u64,
+}
+
+/// Configuration of the Unix domain socket where the daemon listens.
+///
+/// A socket file left behind by a daemon that is no longer running is removed on start. The socket
+/// file is removed when the daemon is stopped.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UnixSocketConfig {
+    /// Path of the socket file.
+    pub path: PathBuf,
+
+    /// Permission bits of the socket file, e.g. `0o600` to allow connections from processes
+    /// running as the same user only.
+    pub mode: u32,
+}
+
+/// TLS certificate chain and private key of the daemon's HTTP server.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TlsConfig {
+    /// PEM-encoded certificate chain, starting with the server certificate.
+    pub cert: PemSource,
+
+    /// PEM-encoded private key of the server certificate.
+    pub key: PemSource,
+}
+
+/// Where to read PEM-encoded data from.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PemSource {
+    /// Read the data from a file.
+    Path(PathBuf),
+    /// Use the data as is.
+    Bytes(Vec<u8>),
+}
+
+impl TlsConfig {
+    /// Read the certificate and the key, returning the PEM data of both.
+    fn load(&self) -> Result<(Vec<u8>, Vec<u8>), TlsError> {
+        let cert = match &self.cert {
+            PemSource::Path(path) => {
+                std::fs::read(path).map_err(|err| TlsError::CannotReadCertificate {
+                    path: path.clone(),
+                    reason: err.to_string(),
+                })?
+            }
+            PemSource::Bytes(data) => data.clone(),
+        };
+        let key = match &self.key {
+            PemSource::Path(path) => {
+                std::fs::read(path).map_err(|err| TlsError::CannotReadKey {
+                    path: path.clone(),
+                    reason: err.to_string(),
+                })?
+            }
+            PemSource::Bytes(data) => data.clone(),
+        };
+        Ok((cert, key))
+    }
+}
+
+/// A running Lassie daemon.
+///
+/// The process can run several daemons at the same time, each one with its own configuration,
+/// Lassie instance and HTTP server.
 pub struct Daemon {
-    port: u16,
-    access_token: Option<String>,
+    // The handle identifying this daemon in calls to the Go library
+    handle: u64,
+    local_addr: SocketAddr,
+    unix_socket: Option<PathBuf>,
+    tls: bool,
+    metrics_port: Option<u16>,
+    access_tokens: Mutex<Vec<AccessToken>>,
+    status: Arc<StatusMonitor>,
+    handler_thread: Option<std::thread::JoinHandle<()>>,
 }
 
 impl Daemon {
     /// # Errors
     ///
-    /// This function returns `Err` when you are trying to start more than instance, the configured
+    /// This function returns `Err` when the configuration is not valid, e.g. the configured
     /// `temp_dir` path cannot be converted to a Go string, or Lassie cannot start the HTTP server.
-    pub fn start(config: DaemonConfig) -> Result<Self, StartError> {
-        log::debug!("[Daemon::start] Locking global daemon mutex");
-        let mut maybe_daemon = get_global_daemon().map_err(|_| StartError::MutexPoisoned)?;
-        if maybe_daemon.is_some() {
-            log::error!("{}", StartError::OnlyOneInstanceAllowed);
-            return Err(StartError::OnlyOneInstanceAllowed);
-        }
-
+    pub fn start(mut config: DaemonConfig) -> Result<Self, StartError> {
         log::info!("Starting Lassie Daemon");
-        let temp_dir = match config.temp_dir {
-            None => String::new(),
-            Some(dir) => {
-                let str = dir.to_str();
-                match str {
-                    None => return Err(StartError::PathIsNotValidUtf8(dir)),
-                    Some(val) => val.to_string(),
-                }
-            }
-        };
-
-        let temp_dir = CString::new(temp_dir.clone())
-            .map_err(|_| StartError::PathContainsNullByte(temp_dir))?;
+        merge_deprecated_access_token(&mut config);
+        let protocols = check_protocols(&config)?;
+        check_limits(&config)?;
+        check_listen_addr(&config)?;
 
-        let log_level = if log::log_enabled!(log::Level::Debug) {
-            log::LevelFilter::Debug
-        } else {
-            log::LevelFilter::Off
-        };
+        let temp_dir = to_c_path(config.temp_dir)?;
+        let cache_dir = to_c_path(config.cache.as_ref().map(|c| c.path.clone()))?;
+        let unix_socket = to_c_path(config.unix_socket.as_ref().map(|s| s.path.clone()))?;
+        let tls = config.tls.as_ref().map(TlsConfig::load).transpose();
+        let (tls_cert, tls_key) = tls.map_err(StartError::Tls)?.unwrap_or_default();
 
-        let global_timeout = match config.global_timeout {
-            Some(d) => try_convert_duration_to_go_type(d)?,
-            None => 0,
-        };
+        // Go logs are forwarded to the `log` crate with the target `lassie::go::{subsystem}`.
+        // The log level is shared by all daemons and follows `log::max_level()` at the time the
+        // last daemon was started.
+        go_log::init();
+        let log_level = log::max_level();
 
-        let provider_timeout = match config.provider_timeout {
-            Some(d) => try_convert_duration_to_go_type(d)?,
-            None => 0,
-        };
+        let global_timeout = config
+            .global_timeout
+            .map_or(Ok(0), try_convert_duration_to_go_type)?;
+        let provider_timeout = config
+            .provider_timeout
+            .map_or(Ok(0), try_convert_duration_to_go_type)?;
 
-        let access_token = config.access_token.clone().unwrap_or_default();
-        let access_token = CString::new(access_token.clone())
-            .map_err(|_| StartError::AccessTokenContainsNullByte(access_token.to_string()))?;
+        let access_tokens = to_c_access_tokens(&config.access_tokens)?;
+        let go_access_tokens = GoAccessToken::from_c_access_tokens(&access_tokens);
 
         // See https://github.com/filecoin-project/lassie/pull/240
-        let lassie_version = env!("LASSIE_VERSION");
-        let lassie_user_agent = format!("lassie/v{lassie_version}");
-        let lassie_user_agent = CString::new(lassie_user_agent.clone()).map_err(|_| {
+        let lassie_user_agent = format!("lassie/v{}", env!("LASSIE_VERSION"));
+        let lassie_user_agent = CString::new(lassie_user_agent).map_err(|_| {
             StartError::Lassie("Internal error: invalid Lassie version.".to_string())
         })?;
 
+        let listen_addr = CString::new(config.listen_addr.to_string()).map_err(|_| {
+            StartError::Lassie("Internal error: invalid listen address.".to_string())
+        })?;
+
+        let indexer_url = config.indexer_url.clone().unwrap_or_default();
+        let indexer_url = CString::new(indexer_url.clone())
+            .map_err(|_| StartError::IndexerUrlContainsNullByte(indexer_url))?;
+
+        let providers =
+            to_c_providers(&config.providers).map_err(StartError::ProviderContainsNullByte)?;
+        let go_providers = GoProvider::from_c_providers(&providers);
+
+        let excluded_providers = CStringArray::new(to_c_peer_ids(&config.excluded_providers)?);
+
+        let go_protocols = protocols
+            .iter()
+            .map(|p| p.as_c_str().as_ptr())
+            .collect::<Vec<_>>();
+
         let go_config = GoDaemonConfig {
             temp_dir: temp_dir.as_ptr(),
+            listen_addr: listen_addr.as_ptr(),
             log_level: log_level as usize,
             port: config.port,
+            unix_socket: unix_socket.as_ptr(),
+            unix_socket_mode: config.unix_socket.as_ref().map_or(0, |s| s.mode),
             global_timeout,
             provider_timeout,
             max_blocks: config.max_blocks.unwrap_or(0),
-            access_token: access_token.as_ptr(),
+            access_tokens: go_access_tokens.as_ptr(),
+            access_tokens_len: go_access_tokens.len(),
             lassie_user_agent: lassie_user_agent.as_ptr(),
+            providers: go_providers.as_ptr(),
+            providers_len: go_providers.len(),
+            indexer_url: indexer_url.as_ptr(),
+            protocols: go_protocols.as_ptr(),
+            protocols_len: go_protocols.len(),
+            libp2p_low_water: config.libp2p_conn_manager.map_or(0, |c| c.low_water),
+            libp2p_high_water: config.libp2p_conn_manager.map_or(0, |c| c.high_water),
+            concurrent_sp_retrievals: config.concurrent_sp_retrievals.unwrap_or(0),
+            bitswap_concurrency: config.bitswap_concurrency.unwrap_or(0),
+            excluded_providers: excluded_providers.as_ptr(),
+            excluded_providers_len: excluded_providers.len(),
+            metrics_enabled: config.metrics.is_some(),
+            metrics_port: config.metrics.map_or(0, |m| m.port),
+            cache_dir: cache_dir.as_ptr(),
+            cache_max_size: config.cache.as_ref().map_or(0, |c| c.max_size),
+            tls_enabled: config.tls.is_some(),
+            tls_cert: tls_cert.as_ptr(),
+            tls_cert_len: tls_cert.len(),
+            tls_key: tls_key.as_ptr(),
+            tls_key_len: tls_key.len(),
         };
 
         // SAFETY:
         // It's safe to call this FFI function as it does not have any special safety requirements
-        // and we know that `&go_config` is not a NULL pointer.
+        // and we know that `&go_config` is not a NULL pointer. All strings and arrays referenced
+        // by `go_config` live until the end of this function.
         let result = unsafe { InitDaemon(&go_config) };
         log::debug!("Lassie.InitDaemon result: {:?}", result);
 
         if let Some(msg) = result.error() {
             log::error!("Lassie.InitDaemon failed: {msg}");
-            return Err(StartError::Lassie(msg));
-        }
-        let port = result.port;
-        log::debug!("Lassie.InitDaemon returned port: {port}");
-
-        let handler_thread = std::thread::spawn(|| {
-            log::debug!("Running Lassie HTTP handler");
-            // SAFETY:
-            // This FFI function is designed to be called from a different thread.
-            let result = unsafe { RunDaemon() };
-            if let Some(msg) = result.error() {
-                log::error!("Lassie HTTP handler failed: {msg}");
-                // TODO: should we somehow notify the main thread about the problem?
-                // Maybe we should panic? That would not kill the main thread though.
-            }
-            log::debug!("HTTP handler exited");
-        });
-        *maybe_daemon = Some(GoDaemon { handler_thread });
+            return Err(StartError::from_go(result.error_kind, msg));
+        }
+        let handle = result.handle;
+        let local_addr = SocketAddr::new(config.listen_addr, result.port);
+        let metrics_port = config.metrics.map(|_| result.metrics_port);
+        log::debug!("Lassie.InitDaemon returned handle {handle} and address: {local_addr}");
 
-        log::info!("Lassie Daemon is listening on port {}", port);
+        let status = Arc::new(StatusMonitor::new());
+        let handler_thread = spawn_handler(handle, status.clone());
+
+        let unix_socket = config.unix_socket.map(|s| s.path);
+        let endpoint = unix_socket.as_ref().map(|p| p.display().to_string());
+        log::info!(
+            "Lassie Daemon is listening on {}",
+            endpoint.unwrap_or_else(|| local_addr.to_string())
+        );
         Ok(Daemon {
-            port,
-            access_token: config.access_token,
+            handle,
+            local_addr,
+            unix_socket,
+            tls: config.tls.is_some(),
+            metrics_port,
+            access_tokens: Mutex::new(config.access_tokens),
+            status,
+            handler_thread: Some(handler_thread),
         })
     }
 
+    /// Retrieve the content identified by `cid` directly from the Lassie instance running inside
+    /// this daemon, without making HTTP requests to the daemon.
+    ///
+    /// The retrieval runs in a background thread and the returned stream yields the CAR bytes as
+    /// they arrive.
     #[must_use]
-    pub fn port(&self) -> u16 {
-        self.port
+    pub fn fetch(&self, cid: Cid, options: FetchOptions) -> CarStream {
+        CarStream::start(self.handle, cid, options)
     }
 
-    #[must_use]
-    pub fn access_token(&self) -> &Option<String> {
-        &self.access_token
+    /// Retrieve the single block identified by `cid` and verify that its data matches the CID.
+    ///
+    /// This is handy for reading small records like DAG-CBOR nodes without parsing CAR streams.
+    ///
+    /// # Errors
+    ///
+    /// This function returns [`FetchError::NotFound`] when no provider has the block,
+    /// [`FetchError::Timeout`] when the retrieval takes too long and [`FetchError::HashMismatch`]
+    /// when the received data does not match the CID.
+    pub fn get_block(&self, cid: Cid) -> Result<Vec<u8>, FetchError> {
+        fetch::get_block(self.handle, &cid)
     }
-}
 
-impl Drop for Daemon {
-    fn drop(&mut self) {
-        log::debug!("[Daemon::drop] Locking global daemon mutex");
-        let mut maybe_daemon = get_global_daemon().expect("global daemon mutex was poisoned");
-        assert!(
-            maybe_daemon.is_some(),
-            "Daemon.drop() was called when no GoDaemon was running"
+    /// Retrieve the file or directory identified by `cid` and write it to `dest`.
+    ///
+    /// Returns the totals of the extraction. Use [`unixfs::extract_with_progress`] together with
+    /// [`Daemon::fetch`] to report progress while the content is being written.
+    ///
+    /// # Errors
+    ///
+    /// This function returns `Err` when the retrieval fails, the content is not a file or
+    /// directory or it cannot be written to `dest`.
+    pub fn fetch_to_path(
+        &self,
+        cid: Cid,
+        dest: impl AsRef<Path>,
+    ) -> Result<unixfs::ExtractProgress, UnixfsError> {
+        let stream = self.fetch(
+            cid,
+            FetchOptions {
+                duplicates: true,
+                ..FetchOptions::default()
+            },
         );
+        unixfs::extract(car::CarReader::new(stream)?, dest)
+    }
+
+    /// Subscribe to events reported by Lassie for all retrievals made by this daemon, including
+    /// retrievals requested via HTTP.
+    ///
+    /// Each call creates a new channel. The channel is closed when the daemon is stopped.
+    #[must_use]
+    pub fn subscribe_events(&self) -> std::sync::mpsc::Receiver<RetrievalEvent> {
+        events::subscribe(self.handle)
+    }
+
+    /// Replace the TLS certificate and private key of the HTTP server without restarting the
+    /// daemon, e.g. after the certificate was renewed. New connections use the new certificate,
+    /// established connections are not affected.
+    ///
+    /// # Errors
+    ///
+    /// This function returns `Err` when the daemon was started without TLS or when the new
+    /// certificate or key cannot be read or is not valid. The daemon keeps using the previous
+    /// certificate in that case.
+    pub fn reload_tls(&self, tls: &TlsConfig) -> Result<(), TlsError> {
+        if !self.tls {
+            return Err(TlsError::NotEnabled);
+        }
+        let (cert, key) = tls.load()?;
+
+        // SAFETY:
+        // It's safe to call this FFI function as it does not have any special safety requirements.
+        // The certificate and key buffers live until the end of this function, Go copies them.
+        let result = unsafe {
+            ReloadTls(
+                self.handle,
+                cert.as_ptr(),
+                cert.len(),
+                key.as_ptr(),
+                key.len(),
+            )
+        };
+        match result.error() {
+            None => {
+                log::info!("Reloaded TLS certificate of the Lassie Daemon");
+                Ok(())
+            }
+            Some(msg) => {
+                log::error!("Lassie.ReloadTls failed: {msg}");
+                Err(TlsError::from_go(result.error_kind, msg.clone())
+                    .unwrap_or(TlsError::Lassie(msg)))
+            }
+        }
+    }
+
+    /// Stop the daemon and wait until the Lassie HTTP handler exits.
+    ///
+    /// Dropping the daemon stops it too, but any errors are only logged.
+    ///
+    /// # Errors
+    ///
+    /// This function returns `Err` when Lassie cannot stop the HTTP server or when the HTTP
+    /// handler thread panicked.
+    pub fn stop(mut self) -> Result<(), StopError> {
+        self.stop_go_daemon()
+    }
 
-        log::debug!("Shutting down Lassie Daemon");
+    fn stop_go_daemon(&mut self) -> Result<(), StopError> {
+        let Some(handler_thread) = self.handler_thread.take() else {
+            return Ok(());
+        };
+        events::unsubscribe_all(self.handle);
+
+        log::debug!("Shutting down Lassie Daemon {}", self.handle);
         // SAFETY:
         // We can call this FFI function as it does not have any special safety requirements.
-        let result = unsafe { StopDaemon() };
+        let result = unsafe { StopDaemon(self.handle) };
         if let Some(msg) = result.error() {
-            panic!("Cannot stop Lassie Daemon: {msg}");
+            return Err(StopError::Lassie(msg));
         }
 
         log::debug!("Waiting for Lassie to exit");
-        // It's safe to call unwrap() here because we already handled maybe_daemon.is_none() above
-        let GoDaemon { handler_thread } = maybe_daemon.take().unwrap();
-        handler_thread.join().expect("Lassie handler panicked");
+        handler_thread
+            .join()
+            .map_err(|_| StopError::HandlerPanicked)
+    }
+
+    /// Report whether the Lassie HTTP handler is still running.
+    ///
+    /// The handler exits when the daemon is stopped or when the HTTP server fails. In the latter
+    /// case, the daemon does not serve any requests and you should start a new one.
+    #[must_use]
+    pub fn status(&self) -> DaemonStatus {
+        self.status.get()
+    }
+
+    /// Block the current thread until the Lassie HTTP handler exits, then return the final status.
+    #[must_use]
+    pub fn wait(&self) -> DaemonStatus {
+        self.status.wait()
+    }
+
+    /// Block the current thread until the Lassie HTTP handler exits or the timeout elapses,
+    /// whichever comes first. Returns the status at that time.
+    #[must_use]
+    pub fn wait_timeout(&self, timeout: Duration) -> DaemonStatus {
+        self.status.wait_timeout(timeout)
+    }
+
+    /// The port where the HTTP server is listening, or zero when the daemon listens on a Unix
+    /// domain socket.
+    #[must_use]
+    pub fn port(&self) -> u16 {
+        self.local_addr.port()
+    }
+
+    /// The address where the HTTP server is listening, including the port chosen by the
+    /// operating system when `DaemonConfig::port` is zero. The port is zero when the daemon
+    /// listens on a Unix domain socket.
+    #[must_use]
+    pub fn local_addr(&self) -> SocketAddr {
+        self.local_addr
+    }
+
+    /// The path of the Unix domain socket where the HTTP server is listening, or `None` when the
+    /// daemon listens on a TCP port.
+    #[must_use]
+    pub fn unix_socket(&self) -> Option<&Path> {
+        self.unix_socket.as_deref()
+    }
+
+    /// Report whether the HTTP server uses TLS.
+    #[must_use]
+    pub fn tls_enabled(&self) -> bool {
+        self.tls
+    }
+
+    /// The port where Prometheus metrics are served at `/metrics`, or `None` when metrics are
+    /// disabled.
+    #[must_use]
+    pub fn metrics_port(&self) -> Option<u16> {
+        self.metrics_port
+    }
+
+    /// The address where Prometheus metrics are served at `/metrics`, or `None` when metrics are
+    /// disabled. It uses the IP address of [`Daemon::local_addr`].
+    #[must_use]
+    pub fn metrics_addr(&self) -> Option<SocketAddr> {
+        self.metrics_port
+            .map(|port| SocketAddr::new(self.local_addr.ip(), port))
+    }
+
+    /// The first of the access tokens accepted by the daemon, or `None` when the daemon does not
+    /// require authorization.
+    #[must_use]
+    pub fn access_token(&self) -> Option<String> {
+        self.lock_access_tokens().first().map(|t| t.token.clone())
+    }
+
+    /// The access tokens accepted by the daemon.
+    #[must_use]
+    pub fn access_tokens(&self) -> Vec<AccessToken> {
+        self.lock_access_tokens().clone()
+    }
+
+    /// The resources consumed by the requests authorized by `token`, or `None` when the daemon
+    /// does not accept this token.
+    ///
+    /// The counters are kept when [`Daemon::set_access_tokens`] keeps the token and reset when
+    /// the token is removed.
+    #[must_use]
+    pub fn access_token_usage(&self, token: &str) -> Option<AccessTokenUsage> {
+        // Tokens containing a null byte are rejected by set_access_tokens
+        let c_token = CString::new(token).ok()?;
+        let mut usage = GoAccessTokenUsage::default();
+        let usage_ptr = std::ptr::addr_of_mut!(usage);
+        // SAFETY:
+        // It's safe to call this FFI function as it does not have any special safety requirements.
+        // The token lives until the end of this function and `usage_ptr` points to a valid value
+        // that Go overwrites.
+        let result = unsafe { GetAccessTokenUsage(self.handle, c_token.as_ptr(), usage_ptr) };
+        if let Some(msg) = result.error() {
+            log::error!("Lassie.GetAccessTokenUsage failed: {msg}");
+            return None;
+        }
+        usage.found.then_some(AccessTokenUsage {
+            active_retrievals: usage.active_retrievals,
+            requests_last_minute: usage.requests_last_minute,
+            bytes_today: usage.bytes_today,
+        })
+    }
+
+    /// Replace the access tokens accepted by the daemon, e.g. to rotate credentials. Requests
+    /// that are already running are not affected. An empty list disables the authorization check.
+    ///
+    /// # Errors
+    ///
+    /// This function returns `Err` when a token is empty or contains a null byte, when a limit of
+    /// its policy is zero, or when the list is empty and the daemon listens on an address that is
+    /// not loopback. The daemon keeps the previous tokens in that case.
+    pub fn set_access_tokens(&self, tokens: Vec<AccessToken>) -> Result<(), AccessTokenError> {
+        let ip = self.local_addr.ip();
+        if tokens.is_empty() && self.unix_socket.is_none() && !ip.is_loopback() {
+            return Err(AccessTokenError::Required(ip));
+        }
+        let c_tokens = to_c_access_tokens(&tokens)?;
+        let go_tokens = GoAccessToken::from_c_access_tokens(&c_tokens);
+
+        // Hold the lock while updating Go, so that concurrent calls are applied in the same order
+        let mut current = self.lock_access_tokens();
+        // SAFETY:
+        // It's safe to call this FFI function as it does not have any special safety requirements.
+        // The tokens live until the end of this function, Go copies them.
+        let result = unsafe { SetAccessTokens(self.handle, go_tokens.as_ptr(), go_tokens.len()) };
+        if let Some(msg) = result.error() {
+            log::error!("Lassie.SetAccessTokens failed: {msg}");
+            return Err(AccessTokenError::Lassie(msg));
+        }
+        *current = tokens;
+        log::info!("Updated access tokens of the Lassie Daemon");
+        Ok(())
+    }
+
+    fn lock_access_tokens(&self) -> std::sync::MutexGuard<'_, Vec<AccessToken>> {
+        self.access_tokens
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+impl Drop for Daemon {
+    fn drop(&mut self) {
+        if let Err(err) = self.stop_go_daemon() {
+            log::error!("{err}");
+        }
     }
 }
 
@@ -293,39 +952,203 @@ fn try_convert_duration_to_go_type(from: Duration) -> Result<i64, StartError> {
     i64::try_from(from.as_nanos()).map_err(|_| StartError::DurationIsTooLong(from))
 }
 
+fn to_c_path(path: Option<PathBuf>) -> Result<CString, StartError> {
+    let path = match path {
+        None => String::new(),
+        Some(dir) => {
+            let str = dir.to_str();
+            match str {
+                None => return Err(StartError::PathIsNotValidUtf8(dir)),
+                Some(val) => val.to_string(),
+            }
+        }
+    };
+
+    CString::new(path.clone()).map_err(|_| StartError::PathContainsNullByte(path))
+}
+
+/// On error, returns the provider address containing a null byte.
+fn to_c_providers(providers: &[Provider]) -> Result<Vec<(CString, &'static CStr)>, String> {
+    providers
+        .iter()
+        .map(|p| {
+            let addr = CString::new(p.addr.clone()).map_err(|_| p.addr.clone())?;
+            let protocol = p.protocol.map_or(c"", Protocol::as_c_str);
+            Ok((addr, protocol))
+        })
+        .collect()
+}
+
+fn to_c_access_tokens(
+    tokens: &[AccessToken],
+) -> Result<Vec<(CString, AccessTokenPolicy)>, AccessTokenError> {
+    tokens
+        .iter()
+        .map(|AccessToken { token, policy }| {
+            if token.is_empty() {
+                return Err(AccessTokenError::IsEmpty);
+            }
+            // Go treats zero as no limit, reject it instead of silently disabling the limit
+            let limits = [
+                (
+                    "max_concurrent_retrievals",
+                    policy.max_concurrent_retrievals.map(u64::from),
+                ),
+                (
+                    "requests_per_minute",
+                    policy.requests_per_minute.map(u64::from),
+                ),
+                ("bytes_per_day", policy.bytes_per_day),
+                ("max_blocks", policy.max_blocks),
+            ];
+            if let Some((name, _)) = limits.into_iter().find(|(_, limit)| *limit == Some(0)) {
+                return Err(AccessTokenError::LimitIsZero(name));
+            }
+            let c_token = CString::new(token.clone())
+                .map_err(|_| AccessTokenError::ContainsNullByte(token.clone()))?;
+            Ok((c_token, *policy))
+        })
+        .collect()
+}
+
+fn to_c_peer_ids(peer_ids: &[String]) -> Result<Vec<CString>, StartError> {
+    peer_ids
+        .iter()
+        .map(|id| {
+            let invalid = |reason: String| StartError::InvalidPeerId {
+                peer_id: id.clone(),
+                reason,
+            };
+            peer_id::validate_peer_id(id).map_err(invalid)?;
+            CString::new(id.clone()).map_err(|_| invalid("contains a null byte".to_string()))
+        })
+        .collect()
+}
+
+fn check_protocols(config: &DaemonConfig) -> Result<Vec<Protocol>, StartError> {
+    let Some(enabled) = &config.protocols else {
+        return Ok(vec![]);
+    };
+
+    if enabled.is_empty() {
+        return Err(StartError::NoProtocolsEnabled);
+    }
+
+    for provider in &config.providers {
+        if let Some(protocol) = provider.protocol {
+            if !enabled.contains(&protocol) {
+                return Err(StartError::ProviderProtocolNotEnabled {
+                    addr: provider.addr.clone(),
+                    protocol,
+                });
+            }
+        }
+    }
+
+    let mut protocols = enabled.iter().copied().collect::<Vec<_>>();
+    protocols.sort();
+    Ok(protocols)
+}
+
+fn check_limits(config: &DaemonConfig) -> Result<(), StartError> {
+    if let Some(ConnManagerConfig {
+        low_water,
+        high_water,
+    }) = config.libp2p_conn_manager
+    {
+        if high_water == 0 || low_water > high_water {
+            return Err(StartError::InvalidConnManagerWatermarks {
+                low_water,
+                high_water,
+            });
+        }
+    }
+
+    if config.concurrent_sp_retrievals == Some(0) {
+        return Err(StartError::ConcurrencyLimitIsZero(
+            "concurrent_sp_retrievals",
+        ));
+    }
+
+    if config.bitswap_concurrency == Some(0) {
+        return Err(StartError::ConcurrencyLimitIsZero("bitswap_concurrency"));
+    }
+
+    if config.cache.as_ref().is_some_and(|c| c.max_size == 0) {
+        return Err(StartError::CacheSizeIsZero);
+    }
+
+    Ok(())
+}
+
+/// Run the Lassie HTTP handler of the daemon in a dedicated thread, reporting its exit to `status`.
+fn spawn_handler(handle: u64, status: Arc<StatusMonitor>) -> std::thread::JoinHandle<()> {
+    std::thread::spawn(move || {
+        log::debug!("Running Lassie HTTP handler");
+        // SAFETY:
+        // This FFI function is designed to be called from a different thread.
+        let result = unsafe { RunDaemon(handle) };
+        let error = result.error();
+        if let Some(msg) = &error {
+            log::error!("Lassie HTTP handler failed: {msg}");
+        }
+        status.set_exited(error);
+        log::debug!("HTTP handler exited");
+    })
+}
+
+#[allow(deprecated)]
+fn merge_deprecated_access_token(config: &mut DaemonConfig) {
+    if let Some(token) = config.access_token.take() {
+        config.access_tokens.insert(0, AccessToken::new(token));
+    }
+}
+
+fn check_listen_addr(config: &DaemonConfig) -> Result<(), StartError> {
+    if config.unix_socket.is_some() {
+        if !cfg!(unix) {
+            return Err(StartError::UnixSocketsNotSupported);
+        }
+        return Ok(());
+    }
+
+    if !config.listen_addr.is_loopback() && config.access_tokens.is_empty() {
+        return Err(StartError::AccessTokenRequired(config.listen_addr));
+    }
+    Ok(())
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
     use pretty_assertions::assert_eq;
 
-    // Rust runs tests in parallel. Since Lassie Daemon is a singleton,
-    // we must synchronise the tests to ensure they run sequentially
-    static TEST_GUARD: Mutex<()> = Mutex::new(());
-
     #[test]
     fn can_start_after_stopping() {
-        let _lock = setup_test_env();
+        setup_test_env();
         let d = Daemon::start(DaemonConfig::default()).expect("cannot start the first time");
-        drop(d);
+        d.stop().expect("cannot stop the first instance");
         let _ = Daemon::start(DaemonConfig::default()).expect("cannot start the second time");
     }
 
     #[test]
-    fn cannot_start_twice() {
-        let _lock = setup_test_env();
-        let _first =
+    fn can_run_multiple_instances() {
+        setup_test_env();
+        let first =
             Daemon::start(DaemonConfig::default()).expect("cannot start the first instance");
-        match Daemon::start(DaemonConfig::default()) {
-            Ok(_) => panic!("starting another instance should have failed"),
+        let second =
+            Daemon::start(DaemonConfig::default()).expect("cannot start the second instance");
+        assert_ne!(first.port(), second.port());
 
-            Err(err) => assert_eq!(err, StartError::OnlyOneInstanceAllowed),
-        };
+        first.stop().expect("cannot stop the first instance");
+        assert_eq!(second.status(), DaemonStatus::Running);
+        second.stop().expect("cannot stop the second instance");
     }
 
     #[test]
     #[cfg_attr(windows, ignore)]
     fn reports_listen_error() {
-        let _lock = setup_test_env();
+        setup_test_env();
         let result = Daemon::start(DaemonConfig {
             port: 1,
             ..DaemonConfig::default()
@@ -341,25 +1164,525 @@ mod test {
                 );
             }
             Err(err) => panic!("unexpected error while starting Lassie on port 1: {err}"),
+        }
+    }
+
+    #[test]
+    fn reports_invalid_provider_address() {
+        setup_test_env();
+        let result = Daemon::start(DaemonConfig {
+            providers: vec![Provider::new("/ip4/127.0.0.1/tcp/3000")],
+            ..DaemonConfig::default()
+        });
+        match result {
+            Ok(_) => panic!("starting Lassie with a provider without peer ID should have failed"),
+            Err(StartError::Lassie(msg)) => {
+                assert!(
+                    msg.contains("invalid providers") && msg.contains("/ip4/127.0.0.1/tcp/3000"),
+                    "Expected invalid provider error, actual: {msg}",
+                );
+            }
+            Err(err) => panic!("unexpected error while starting Lassie: {err}"),
+        }
+    }
+
+    #[test]
+    fn reports_invalid_indexer_url() {
+        setup_test_env();
+        let result = Daemon::start(DaemonConfig {
+            indexer_url: Some("not a url".to_string()),
+            ..DaemonConfig::default()
+        });
+        match result {
+            Ok(_) => panic!("starting Lassie with an invalid indexer URL should have failed"),
+            Err(StartError::Lassie(msg)) => {
+                assert!(
+                    msg.contains("invalid indexer URL") && msg.contains("not a url"),
+                    "Expected invalid indexer URL error, actual: {msg}",
+                );
+            }
+            Err(err) => panic!("unexpected error while starting Lassie: {err}"),
+        }
+    }
+
+    #[test]
+    fn rejects_empty_protocol_set() {
+        setup_test_env();
+        let result = Daemon::start(DaemonConfig {
+            protocols: Some(HashSet::new()),
+            ..DaemonConfig::default()
+        });
+        match result {
+            Ok(_) => panic!("starting Lassie with no protocols should have failed"),
+            Err(err) => assert_eq!(err, StartError::NoProtocolsEnabled),
+        }
+    }
+
+    #[test]
+    fn rejects_provider_with_disabled_protocol() {
+        setup_test_env();
+        let addr =
+            "/ip4/127.0.0.1/tcp/3000/p2p/12D3KooWHgbQZvPjSx2DURHBkvBJWvHWP4ChoNHBzVNG3Lk2Jzfi";
+        let result = Daemon::start(DaemonConfig {
+            providers: vec![Provider::new(addr).with_protocol(Protocol::Graphsync)],
+            protocols: Some(HashSet::from([Protocol::Http, Protocol::Bitswap])),
+            ..DaemonConfig::default()
+        });
+        match result {
+            Ok(_) => {
+                panic!("starting Lassie with a provider using disabled protocol should have failed")
+            }
+            Err(err) => assert_eq!(
+                err,
+                StartError::ProviderProtocolNotEnabled {
+                    addr: addr.to_string(),
+                    protocol: Protocol::Graphsync
+                }
+            ),
+        }
+    }
+
+    #[test]
+    fn rejects_low_water_above_high_water() {
+        setup_test_env();
+        let result = Daemon::start(DaemonConfig {
+            libp2p_conn_manager: Some(ConnManagerConfig {
+                low_water: 20,
+                high_water: 10,
+            }),
+            ..DaemonConfig::default()
+        });
+        match result {
+            Ok(_) => panic!("starting Lassie with low_water > high_water should have failed"),
+            Err(err) => assert_eq!(
+                err,
+                StartError::InvalidConnManagerWatermarks {
+                    low_water: 20,
+                    high_water: 10
+                }
+            ),
+        }
+    }
+
+    #[test]
+    fn rejects_zero_high_water() {
+        setup_test_env();
+        let result = Daemon::start(DaemonConfig {
+            libp2p_conn_manager: Some(ConnManagerConfig {
+                low_water: 0,
+                high_water: 0,
+            }),
+            ..DaemonConfig::default()
+        });
+        match result {
+            Ok(_) => panic!("starting Lassie with high_water=0 should have failed"),
+            Err(err) => assert_eq!(
+                err,
+                StartError::InvalidConnManagerWatermarks {
+                    low_water: 0,
+                    high_water: 0
+                }
+            ),
+        }
+    }
+
+    #[test]
+    fn rejects_zero_concurrency_limits() {
+        setup_test_env();
+        let result = Daemon::start(DaemonConfig {
+            concurrent_sp_retrievals: Some(0),
+            ..DaemonConfig::default()
+        });
+        match result {
+            Ok(_) => panic!("starting Lassie with concurrent_sp_retrievals=0 should have failed"),
+            Err(err) => assert_eq!(
+                err,
+                StartError::ConcurrencyLimitIsZero("concurrent_sp_retrievals")
+            ),
+        }
+
+        let result = Daemon::start(DaemonConfig {
+            bitswap_concurrency: Some(0),
+            ..DaemonConfig::default()
+        });
+        match result {
+            Ok(_) => panic!("starting Lassie with bitswap_concurrency=0 should have failed"),
+            Err(err) => assert_eq!(
+                err,
+                StartError::ConcurrencyLimitIsZero("bitswap_concurrency")
+            ),
+        }
+    }
+
+    #[test]
+    fn can_start_with_connection_and_concurrency_limits() {
+        setup_test_env();
+        let _daemon = Daemon::start(DaemonConfig {
+            libp2p_conn_manager: Some(ConnManagerConfig {
+                low_water: 10,
+                high_water: 20,
+            }),
+            concurrent_sp_retrievals: Some(2),
+            bitswap_concurrency: Some(4),
+            ..DaemonConfig::default()
+        })
+        .expect("cannot start Lassie with connection and concurrency limits");
+    }
+
+    #[test]
+    fn rejects_zero_cache_size() {
+        setup_test_env();
+        let result = Daemon::start(DaemonConfig {
+            cache: Some(CacheConfig {
+                path: std::env::temp_dir().join("rusty-lassie-zero-cache"),
+                max_size: 0,
+            }),
+            ..DaemonConfig::default()
+        });
+        match result {
+            Ok(_) => panic!("starting Lassie with a zero cache size should have failed"),
+            Err(err) => assert_eq!(err, StartError::CacheSizeIsZero),
+        }
+    }
+
+    #[test]
+    fn requires_access_token_for_non_loopback_address() {
+        setup_test_env();
+        let listen_addr = IpAddr::V4(Ipv4Addr::UNSPECIFIED);
+        let result = Daemon::start(DaemonConfig {
+            listen_addr,
+            ..DaemonConfig::default()
+        });
+        match result {
+            Ok(_) => {
+                panic!("listening on all interfaces without an access token should have failed")
+            }
+            Err(err) => assert_eq!(err, StartError::AccessTokenRequired(listen_addr)),
+        }
+    }
+
+    #[test]
+    fn listens_on_ipv6_loopback() {
+        setup_test_env();
+        let listen_addr = IpAddr::V6(std::net::Ipv6Addr::LOCALHOST);
+        let daemon = Daemon::start(DaemonConfig {
+            listen_addr,
+            ..DaemonConfig::default()
+        })
+        .expect("cannot start Lassie on the IPv6 loopback address");
+
+        let local_addr = daemon.local_addr();
+        assert_eq!(local_addr.ip(), listen_addr);
+        assert_ne!(local_addr.port(), 0);
+        std::net::TcpStream::connect(local_addr).expect("cannot connect to the daemon");
+        daemon.stop().expect("cannot stop Lassie");
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn serves_over_unix_socket() {
+        use std::io::{Read, Write};
+        use std::os::unix::fs::PermissionsExt;
+        use std::os::unix::net::{UnixListener, UnixStream};
+
+        setup_test_env();
+        let dir = std::env::temp_dir().join(format!("rusty-lassie-socket-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("lassie.sock");
+
+        // Leave a stale socket file behind, the daemon should replace it
+        let _ = std::fs::remove_file(&path);
+        drop(UnixListener::bind(&path).unwrap());
+
+        let daemon = Daemon::start(DaemonConfig {
+            unix_socket: Some(UnixSocketConfig {
+                path: path.clone(),
+                mode: 0o600,
+            }),
+            ..DaemonConfig::default()
+        })
+        .expect("cannot start Lassie on a Unix socket");
+        assert_eq!(daemon.unix_socket(), Some(path.as_path()));
+        assert_eq!(daemon.port(), 0);
+
+        let mode = std::fs::metadata(&path).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o600);
+
+        let mut stream = UnixStream::connect(&path).expect("cannot connect to the daemon");
+        stream
+            .write_all(b"GET /ipfs/not-a-cid HTTP/1.0\r\n\r\n")
+            .unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+        assert!(
+            response.starts_with("HTTP/1.0 400"),
+            "unexpected response: {response}"
+        );
+
+        daemon.stop().expect("cannot stop Lassie");
+        assert!(!path.exists(), "the socket file should be removed");
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    #[cfg(not(unix))]
+    fn rejects_unix_socket_on_unsupported_platform() {
+        setup_test_env();
+        let result = Daemon::start(DaemonConfig {
+            unix_socket: Some(UnixSocketConfig {
+                path: std::env::temp_dir().join("lassie.sock"),
+                mode: 0o600,
+            }),
+            ..DaemonConfig::default()
+        });
+        match result {
+            Ok(_) => panic!("starting Lassie on a Unix socket should have failed"),
+            Err(err) => assert_eq!(err, StartError::UnixSocketsNotSupported),
+        }
+    }
+
+    fn test_tls_config() -> TlsConfig {
+        let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/testdata/tls");
+        TlsConfig {
+            cert: PemSource::Path(dir.join("cert.pem")),
+            key: PemSource::Path(dir.join("key.pem")),
+        }
+    }
+
+    #[test]
+    fn serves_over_tls_and_reloads_certificate() {
+        use std::io::{Read, Write};
+
+        setup_test_env();
+        let tls = test_tls_config();
+        let daemon = Daemon::start(DaemonConfig {
+            tls: Some(tls.clone()),
+            ..DaemonConfig::default()
+        })
+        .expect("cannot start Lassie with TLS");
+        assert!(daemon.tls_enabled());
+
+        // Go's HTTP server answers plaintext requests sent to a TLS listener with an error
+        let mut stream = std::net::TcpStream::connect(daemon.local_addr()).unwrap();
+        stream.write_all(b"GET /ipfs/ HTTP/1.0\r\n\r\n").unwrap();
+        let mut response = String::new();
+        let _ = stream.read_to_string(&mut response);
+        assert!(
+            response.contains("HTTP request to an HTTPS server"),
+            "unexpected response: {response}"
+        );
+
+        daemon
+            .reload_tls(&tls)
+            .expect("cannot reload the certificate");
+
+        let mismatched = TlsConfig {
+            key: PemSource::Bytes(include_bytes!("../tests/testdata/tls/other-key.pem").to_vec()),
+            ..tls
         };
+        assert!(matches!(
+            daemon.reload_tls(&mismatched),
+            Err(TlsError::KeyMismatch(_))
+        ));
+        daemon.stop().expect("cannot stop Lassie");
+    }
+
+    #[test]
+    fn reports_invalid_tls_certificate() {
+        setup_test_env();
+        let result = Daemon::start(DaemonConfig {
+            tls: Some(TlsConfig {
+                cert: PemSource::Bytes(b"not a certificate".to_vec()),
+                ..test_tls_config()
+            }),
+            ..DaemonConfig::default()
+        });
+        match result {
+            Ok(_) => panic!("starting Lassie with an invalid certificate should have failed"),
+            Err(StartError::Tls(TlsError::InvalidCertificate(_))) => (),
+            Err(err) => panic!("unexpected error: {err}"),
+        }
+    }
+
+    #[test]
+    fn reports_unreadable_tls_key() {
+        setup_test_env();
+        let path = PathBuf::from("/this/file/does/not/exist.pem");
+        let result = Daemon::start(DaemonConfig {
+            tls: Some(TlsConfig {
+                key: PemSource::Path(path.clone()),
+                ..test_tls_config()
+            }),
+            ..DaemonConfig::default()
+        });
+        match result {
+            Ok(_) => panic!("starting Lassie with a missing key file should have failed"),
+            Err(StartError::Tls(TlsError::CannotReadKey { path: actual, .. })) => {
+                assert_eq!(actual, path);
+            }
+            Err(err) => panic!("unexpected error: {err}"),
+        }
+    }
+
+    #[test]
+    fn rejects_malformed_excluded_provider() {
+        setup_test_env();
+        let result = Daemon::start(DaemonConfig {
+            excluded_providers: vec![
+                "12D3KooWHgbQZvPjSx2DURHBkvBJWvHWP4ChoNHBzVNG3Lk2Jzfi".to_string(),
+                "not-a-peer-id".to_string(),
+            ],
+            ..DaemonConfig::default()
+        });
+        match result {
+            Ok(_) => panic!("starting Lassie with a malformed peer ID should have failed"),
+            Err(StartError::InvalidPeerId { peer_id, .. }) => {
+                assert_eq!(peer_id, "not-a-peer-id");
+            }
+            Err(err) => panic!("unexpected error while starting Lassie: {err}"),
+        }
+    }
+
+    #[test]
+    fn reports_status_of_http_handler() {
+        setup_test_env();
+        let mut daemon = Daemon::start(DaemonConfig::default()).expect("cannot start Lassie");
+        assert_eq!(daemon.status(), DaemonStatus::Running);
+        assert_eq!(
+            daemon.wait_timeout(Duration::from_millis(100)),
+            DaemonStatus::Running
+        );
+
+        // Stop the Go daemon behind our back to simulate the HTTP handler exiting. Taking the
+        // handler thread marks the daemon as stopped, so that `Drop` does not stop it again.
+        let handler_thread = daemon
+            .handler_thread
+            .take()
+            .expect("handler thread is running");
+        events::unsubscribe_all(daemon.handle);
+        // SAFETY: We can call this FFI function as it does not have any special safety requirements.
+        let result = unsafe { StopDaemon(daemon.handle) };
+        assert_eq!(result.error(), None);
+        handler_thread.join().expect("handler thread panicked");
+
+        let status = daemon.wait();
+        assert!(
+            matches!(status, DaemonStatus::Exited(_)),
+            "Expected the handler to exit, actual status: {status:?}"
+        );
+        assert_eq!(daemon.status(), status);
+    }
+
+    #[test]
+    fn serves_metrics_on_separate_port() {
+        setup_test_env();
+        let daemon = Daemon::start(DaemonConfig {
+            metrics: Some(MetricsConfig::default()),
+            ..DaemonConfig::default()
+        })
+        .expect("cannot start Lassie with metrics enabled");
+
+        let metrics_port = daemon.metrics_port().expect("metrics port is not reported");
+        assert_ne!(metrics_port, daemon.port());
+
+        let metrics_addr = daemon
+            .metrics_addr()
+            .expect("metrics address is not reported");
+        assert_eq!(metrics_addr.port(), metrics_port);
+
+        // Any response counts, the CID is invalid to get a quick rejection
+        let url = format!("http://{}/ipfs/not-a-cid", daemon.local_addr());
+        let _ = ureq::get(&url)
+            .set("Accept", "application/vnd.ipld.car")
+            .call();
+
+        let body = ureq::get(&format!("http://{metrics_addr}/metrics"))
+            .call()
+            .expect("cannot fetch metrics")
+            .into_string()
+            .expect("cannot read metrics");
+        assert!(
+            body.contains("lassie_retrievals_total"),
+            "Expected Lassie metrics, actual: {body}"
+        );
+        assert!(
+            body.contains("lassie_http_requests_total{code=\""),
+            "Expected HTTP request metrics, actual: {body}"
+        );
+    }
+
+    #[test]
+    fn metrics_are_disabled_by_default() {
+        setup_test_env();
+        let daemon = Daemon::start(DaemonConfig::default()).expect("cannot start Lassie");
+        assert_eq!(daemon.metrics_port(), None);
     }
 
     #[test]
     fn start_returns_access_token() {
-        let token = Some("super_secret".to_string());
-        let _lock = setup_test_env();
+        let token = "super_secret".to_string();
+        setup_test_env();
         let result = Daemon::start(DaemonConfig {
-            access_token: token.clone(),
+            access_tokens: vec![AccessToken::new(token.clone())],
             ..DaemonConfig::default()
         })
         .expect("cannot start Lassie daemon");
 
-        assert_eq!(*result.access_token(), token);
+        assert_eq!(result.access_token(), Some(token));
+    }
+
+    #[test]
+    #[allow(deprecated)]
+    fn accepts_deprecated_access_token() {
+        setup_test_env();
+        let result = Daemon::start(DaemonConfig {
+            access_token: Some("legacy".to_string()),
+            access_tokens: vec![AccessToken::new("rotated")],
+            ..DaemonConfig::default()
+        })
+        .expect("cannot start Lassie daemon");
+
+        assert_eq!(result.access_token(), Some("legacy".to_string()));
+        assert_eq!(
+            result.access_tokens(),
+            vec![AccessToken::new("legacy"), AccessToken::new("rotated")]
+        );
+    }
+
+    #[test]
+    fn rejects_empty_access_token() {
+        setup_test_env();
+        let result = Daemon::start(DaemonConfig {
+            access_tokens: vec![AccessToken::new("")],
+            ..DaemonConfig::default()
+        });
+        match result {
+            Ok(_) => panic!("starting Lassie with an empty access token should have failed"),
+            Err(err) => assert_eq!(err, StartError::AccessTokenIsEmpty),
+        }
+    }
+
+    #[test]
+    fn rejects_zero_access_token_limit() {
+        setup_test_env();
+        let policy = AccessTokenPolicy {
+            requests_per_minute: Some(0),
+            ..AccessTokenPolicy::default()
+        };
+        let result = Daemon::start(DaemonConfig {
+            access_tokens: vec![AccessToken::new("super_secret").with_policy(policy)],
+            ..DaemonConfig::default()
+        });
+        match result {
+            Ok(_) => panic!("starting Lassie with a zero access token limit should have failed"),
+            Err(err) => assert_eq!(
+                err,
+                StartError::AccessTokenLimitIsZero("requests_per_minute")
+            ),
+        }
     }
 
-    fn setup_test_env() -> MutexGuard<'static, ()> {
+    fn setup_test_env() {
         let _ = env_logger::builder().is_test(true).try_init();
-        let lock = TEST_GUARD.lock().expect("cannot obtain global test lock. This typically happens when one of the test fails; the problem should go away after you fix the test failure.");
-        lock
     }
 }
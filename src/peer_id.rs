@@ -0,0 +1,74 @@
+use cid::multibase::Base;
+use cid::multihash::Multihash;
+use cid::Cid;
+
+// See https://github.com/libp2p/specs/blob/master/peer-ids/peer-ids.md
+const LIBP2P_KEY_CODEC: u64 = 0x72;
+const IDENTITY_HASH: u64 = 0x00;
+const SHA2_256_HASH: u64 = 0x12;
+const MAX_INLINE_KEY_LENGTH: u8 = 42;
+
+/// Check that `value` is a valid libp2p peer ID in one of the supported string representations:
+/// a `base58btc`-encoded multihash (`Qm...`, `12D3KooW...`) or a `CIDv1` with `libp2p-key` codec.
+///
+/// Returns a description of the problem when the value is not valid.
+pub(crate) fn validate_peer_id(value: &str) -> Result<(), String> {
+    let hash = if value.starts_with('Q') || value.starts_with('1') {
+        let bytes = Base::Base58Btc
+            .decode(value)
+            .map_err(|err| format!("not a base58btc string: {err}"))?;
+        Multihash::<64>::from_bytes(&bytes).map_err(|err| format!("not a multihash: {err}"))?
+    } else {
+        let cid = Cid::try_from(value).map_err(|err| format!("not a CID: {err}"))?;
+        if cid.codec() != LIBP2P_KEY_CODEC {
+            return Err(format!(
+                "CID codec must be libp2p-key (0x72), found {:#x}",
+                cid.codec()
+            ));
+        }
+        *cid.hash()
+    };
+
+    match hash.code() {
+        IDENTITY_HASH if hash.size() <= MAX_INLINE_KEY_LENGTH => Ok(()),
+        IDENTITY_HASH => Err(format!(
+            "identity multihash is too long ({} bytes)",
+            hash.size()
+        )),
+        SHA2_256_HASH if hash.size() == 32 => Ok(()),
+        SHA2_256_HASH => Err(format!(
+            "sha2-256 multihash has invalid length ({} bytes)",
+            hash.size()
+        )),
+        code => Err(format!("unsupported multihash function {code:#x}")),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn accepts_valid_peer_ids() {
+        for value in [
+            "12D3KooWHgbQZvPjSx2DURHBkvBJWvHWP4ChoNHBzVNG3Lk2Jzfi",
+            "QmYyQSo1c1Ym7orWxLYvCrM2EmxFTANf8wXmmE7DWjhx5N",
+            "bafzaajaiaejcbrkaat7qgkynmxcxcoqymqjpptccncdm6hqh5rhonmtamndnygxn",
+        ] {
+            assert_eq!(validate_peer_id(value), Ok(()), "{value}");
+        }
+    }
+
+    #[test]
+    fn rejects_malformed_peer_ids() {
+        for value in [
+            "",
+            "12D3KooW",
+            "not-a-peer-id",
+            // a CID of a raw block, not a libp2p-key
+            "bafkreih25dih6ug3xtj73vswccw423b56ilrwmnos4cbwhrceudopdp5sq",
+        ] {
+            assert!(validate_peer_id(value).is_err(), "{value}");
+        }
+    }
+}
@@ -0,0 +1,50 @@
+use std::ffi::CStr;
+use std::fmt::{Display, Formatter};
+
+/// Retrieval protocols supported by Lassie.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum Protocol {
+    /// IPFS Bitswap protocol.
+    Bitswap,
+    /// Filecoin Graphsync protocol (`graphsync-filecoinv1`).
+    Graphsync,
+    /// IPFS Trustless HTTP Gateway protocol.
+    Http,
+}
+
+impl Protocol {
+    /// The name Lassie uses for this protocol, e.g. in the `protocols` query parameter.
+    #[must_use]
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Protocol::Bitswap => "bitswap",
+            Protocol::Graphsync => "graphsync",
+            Protocol::Http => "http",
+        }
+    }
+
+    // The names must be kept in sync with parseProtocol() in go-lib/lassie-ffi.go
+    pub(crate) fn as_c_str(self) -> &'static CStr {
+        match self {
+            Protocol::Bitswap => c"bitswap",
+            Protocol::Graphsync => c"graphsync",
+            Protocol::Http => c"http",
+        }
+    }
+
+    // The names must be kept in sync with protocolName() in go-lib/lassie-ffi.go
+    pub(crate) fn from_name(name: &str) -> Option<Protocol> {
+        match name {
+            "bitswap" => Some(Protocol::Bitswap),
+            "graphsync" => Some(Protocol::Graphsync),
+            "http" => Some(Protocol::Http),
+            _ => None,
+        }
+    }
+}
+
+impl Display for Protocol {
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
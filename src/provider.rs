@@ -0,0 +1,33 @@
+use crate::Protocol;
+
+/// A storage provider (peer) to retrieve the content from.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Provider {
+    /// Multiaddr of the provider, including the peer ID.
+    ///
+    /// For example: `/ip4/127.0.0.1/tcp/3000/p2p/12D3KooWHgbQZvPjSx2DURHBkvBJWvHWP4ChoNHBzVNG3Lk2Jzfi`
+    pub addr: String,
+
+    /// The protocol to use when retrieving from this provider.
+    ///
+    /// When not specified, Lassie asks the provider which protocols it supports
+    /// using libp2p identify.
+    pub protocol: Option<Protocol>,
+}
+
+impl Provider {
+    /// Create a provider from a multiaddr, letting Lassie discover the supported protocols.
+    pub fn new(addr: impl Into<String>) -> Self {
+        Provider {
+            addr: addr.into(),
+            protocol: None,
+        }
+    }
+
+    /// Set the protocol to use when retrieving from this provider.
+    #[must_use]
+    pub fn with_protocol(mut self, protocol: Protocol) -> Self {
+        self.protocol = Some(protocol);
+        self
+    }
+}
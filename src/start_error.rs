@@ -1,27 +1,60 @@
 use std::fmt::{Display, Formatter};
+use std::net::IpAddr;
 use std::path::PathBuf;
 use std::time::Duration;
 
+use crate::{AccessTokenError, Protocol, TlsError};
+
 #[derive(Debug, PartialEq, Clone)]
 #[non_exhaustive]
 pub enum StartError {
-    MutexPoisoned,
-    OnlyOneInstanceAllowed,
     PathContainsNullByte(String),
     PathIsNotValidUtf8(PathBuf),
     DurationIsTooLong(Duration),
     Lassie(String),
     AccessTokenContainsNullByte(String),
+    AccessTokenIsEmpty,
+    AccessTokenLimitIsZero(&'static str),
+    ProviderContainsNullByte(String),
+    IndexerUrlContainsNullByte(String),
+    NoProtocolsEnabled,
+    ProviderProtocolNotEnabled { addr: String, protocol: Protocol },
+    InvalidConnManagerWatermarks { low_water: u32, high_water: u32 },
+    ConcurrencyLimitIsZero(&'static str),
+    InvalidPeerId { peer_id: String, reason: String },
+    CacheSizeIsZero,
+    AccessTokenRequired(IpAddr),
+    UnixSocketsNotSupported,
+    Tls(TlsError),
+}
+
+impl StartError {
+    pub(crate) fn from_go(kind: std::ffi::c_int, msg: String) -> Self {
+        match TlsError::from_go(kind, msg.clone()) {
+            Some(err) => StartError::Tls(err),
+            None => StartError::Lassie(msg),
+        }
+    }
+}
+
+impl From<AccessTokenError> for StartError {
+    fn from(err: AccessTokenError) -> Self {
+        match err {
+            AccessTokenError::ContainsNullByte(token) => {
+                StartError::AccessTokenContainsNullByte(token)
+            }
+            AccessTokenError::IsEmpty => StartError::AccessTokenIsEmpty,
+            AccessTokenError::LimitIsZero(limit) => StartError::AccessTokenLimitIsZero(limit),
+            AccessTokenError::Required(addr) => StartError::AccessTokenRequired(addr),
+            AccessTokenError::Lassie(msg) => StartError::Lassie(msg),
+        }
+    }
 }
 
 impl Display for StartError {
     fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
         write!(f, "failed to start Lassie daemon: ")?;
         match self {
-            StartError::MutexPoisoned => f.write_str("the global mutex was poisoned"),
-            StartError::OnlyOneInstanceAllowed => {
-                f.write_str("cannot create more than one instance")
-            }
             StartError::PathContainsNullByte(path_str) => f.write_fmt(format_args!(
                 "null bytes are not allowed in paths (value: {path_str:?})",
             )),
@@ -36,6 +69,44 @@ impl Display for StartError {
             StartError::AccessTokenContainsNullByte(token) => f.write_fmt(format_args!(
                 "null bytes are not allowed in the access token (value: {token:?})",
             )),
+            StartError::AccessTokenIsEmpty => f.write_str("access tokens must not be empty"),
+            StartError::AccessTokenLimitIsZero(limit) => f.write_fmt(format_args!(
+                "{limit} of an access token must be greater than zero",
+            )),
+            StartError::ProviderContainsNullByte(addr) => f.write_fmt(format_args!(
+                "null bytes are not allowed in provider addresses (value: {addr:?})",
+            )),
+            StartError::IndexerUrlContainsNullByte(url) => f.write_fmt(format_args!(
+                "null bytes are not allowed in the indexer URL (value: {url:?})",
+            )),
+            StartError::NoProtocolsEnabled => {
+                f.write_str("at least one retrieval protocol must be enabled")
+            }
+            StartError::ProviderProtocolNotEnabled { addr, protocol } => f.write_fmt(format_args!(
+                "provider {addr:?} is configured to use protocol {protocol}, which is not enabled",
+            )),
+            StartError::InvalidConnManagerWatermarks {
+                low_water,
+                high_water,
+            } => f.write_fmt(format_args!(
+                "invalid libp2p connection manager watermarks (low: {low_water}, high: {high_water}), high_water must be positive and not lower than low_water",
+            )),
+            StartError::ConcurrencyLimitIsZero(option) => f.write_fmt(format_args!(
+                "{option} must be greater than zero",
+            )),
+            StartError::CacheSizeIsZero => {
+                f.write_str("the block cache size limit must be greater than zero")
+            }
+            StartError::AccessTokenRequired(addr) => f.write_fmt(format_args!(
+                "an access token is required when listening on address {addr}, which is not a loopback address",
+            )),
+            StartError::UnixSocketsNotSupported => {
+                f.write_str("Unix domain sockets are not supported on this platform")
+            }
+            StartError::Tls(err) => Display::fmt(err, f),
+            StartError::InvalidPeerId { peer_id, reason } => f.write_fmt(format_args!(
+                "invalid peer ID {peer_id:?}: {reason}",
+            )),
         }
     }
 }
@@ -50,13 +121,13 @@ mod test {
 
     #[test]
     fn can_be_converted_to_anyhow_error() {
-        let result: Result<(), StartError> = Err(StartError::OnlyOneInstanceAllowed);
+        let result: Result<(), StartError> = Err(StartError::NoProtocolsEnabled);
         let anyhow = result.context("lassie error");
         // the test passes when the compiler does not complain about the line above
         // to double check, we are also asserting on the string representation
         assert_eq!(
             format!("{:#}", anyhow.unwrap_err()),
-            format!("lassie error: {}", StartError::OnlyOneInstanceAllowed)
+            format!("lassie error: {}", StartError::NoProtocolsEnabled)
         );
     }
 }
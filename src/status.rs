@@ -0,0 +1,57 @@
+use std::sync::{Condvar, Mutex, MutexGuard, PoisonError};
+use std::time::Duration;
+
+/// Status of the Lassie HTTP handler running inside a [`Daemon`](crate::Daemon).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DaemonStatus {
+    /// The HTTP handler is serving requests.
+    Running,
+    /// The HTTP handler exited, either because the daemon was stopped or because of an error.
+    /// Contains the error reported by Lassie, if any.
+    Exited(Option<String>),
+}
+
+/// Shares the status of the HTTP handler between the handler thread and the `Daemon`.
+pub(crate) struct StatusMonitor {
+    status: Mutex<DaemonStatus>,
+    changed: Condvar,
+}
+
+impl StatusMonitor {
+    pub(crate) fn new() -> Self {
+        StatusMonitor {
+            status: Mutex::new(DaemonStatus::Running),
+            changed: Condvar::new(),
+        }
+    }
+
+    pub(crate) fn set_exited(&self, error: Option<String>) {
+        *self.lock() = DaemonStatus::Exited(error);
+        self.changed.notify_all();
+    }
+
+    pub(crate) fn get(&self) -> DaemonStatus {
+        self.lock().clone()
+    }
+
+    pub(crate) fn wait(&self) -> DaemonStatus {
+        let status = self
+            .changed
+            .wait_while(self.lock(), |s| *s == DaemonStatus::Running)
+            .unwrap_or_else(PoisonError::into_inner);
+        status.clone()
+    }
+
+    pub(crate) fn wait_timeout(&self, timeout: Duration) -> DaemonStatus {
+        let (status, _) = self
+            .changed
+            .wait_timeout_while(self.lock(), timeout, |s| *s == DaemonStatus::Running)
+            .unwrap_or_else(PoisonError::into_inner);
+        status.clone()
+    }
+
+    fn lock(&self) -> MutexGuard<'_, DaemonStatus> {
+        // The status is always in a consistent state, we can ignore poisoning
+        self.status.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
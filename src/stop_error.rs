@@ -0,0 +1,20 @@
+use std::fmt::{Display, Formatter};
+
+#[derive(Debug, PartialEq, Clone)]
+#[non_exhaustive]
+pub enum StopError {
+    Lassie(String),
+    HandlerPanicked,
+}
+
+impl Display for StopError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+        write!(f, "failed to stop Lassie daemon: ")?;
+        match self {
+            StopError::Lassie(msg) => f.write_str(msg),
+            StopError::HandlerPanicked => f.write_str("the HTTP handler thread panicked"),
+        }
+    }
+}
+
+impl std::error::Error for StopError {}
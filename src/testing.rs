@@ -0,0 +1,494 @@
+//! Helpers for testing code that uses Lassie without access to the public IPFS network.
+//!
+//! [`TestProvider`] is a local stand-in for a storage provider. It serves blocks loaded from CAR
+//! fixtures (or generated by [`TestProvider::add_file`]) over the
+//! [Trustless HTTP Gateway](https://specs.ipfs.tech/http-gateways/trustless-gateway/) protocol,
+//! and it can start a [`Daemon`] that retrieves content from this provider only.
+//!
+//! ```no_run
+//! use lassie::testing::TestProvider;
+//!
+//! let provider = TestProvider::start().unwrap();
+//! let cid = provider.add_file(&[0u8; 10_000], 1024);
+//! let daemon = provider.start_daemon().unwrap();
+//! let block = daemon.get_block(cid).unwrap();
+//! ```
+//!
+//! The provider understands dag-pb and raw blocks, `dag-scope` and `dups`. Paths are resolved
+//! through plain (not sharded) directories. `entity-bytes` is ignored, the entire entity is
+//! returned.
+
+use std::collections::{HashMap, HashSet};
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{Shutdown, SocketAddr, TcpListener, TcpStream};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread::JoinHandle;
+use std::time::Duration;
+
+use cid::Cid;
+
+use crate::car::encode::{block_cid, car_block, car_header};
+use crate::car::{CarError, CarReader};
+use crate::unixfs::encode::{write_bytes_field, write_varint_field};
+use crate::unixfs::{self, DAG_PB, RAW, TYPE_DIRECTORY, TYPE_FILE, TYPE_HAMT_SHARD};
+use crate::{Daemon, DaemonConfig, Protocol, Provider, StartError};
+
+/// The peer ID announced for every test provider. Lassie does not verify the identity of HTTP
+/// providers, any valid peer ID works.
+pub const PEER_ID: &str = "12D3KooWHgbQZvPjSx2DURHBkvBJWvHWP4ChoNHBzVNG3Lk2Jzfi";
+
+/// A local provider serving blocks over the Trustless HTTP Gateway protocol.
+///
+/// The provider listens on `127.0.0.1` and stops when dropped.
+pub struct TestProvider {
+    addr: SocketAddr,
+    state: Arc<ProviderState>,
+    accept_thread: Option<JoinHandle<()>>,
+}
+
+#[derive(Default)]
+struct ProviderState {
+    blocks: Mutex<HashMap<Cid, Vec<u8>>>,
+    block_delay: Mutex<Duration>,
+    stopped: AtomicBool,
+}
+
+impl TestProvider {
+    /// Start a provider listening on a free ephemeral port. The provider has no blocks.
+    ///
+    /// # Errors
+    ///
+    /// This function returns `Err` when the TCP listener cannot be created.
+    pub fn start() -> std::io::Result<Self> {
+        let listener = TcpListener::bind(("127.0.0.1", 0))?;
+        let addr = listener.local_addr()?;
+        let state = Arc::new(ProviderState::default());
+
+        let thread_state = state.clone();
+        let accept_thread = std::thread::Builder::new()
+            .name("lassie-test-provider".into())
+            .spawn(move || accept_connections(&listener, &thread_state))?;
+
+        log::debug!("Test provider is listening on {addr}");
+        Ok(TestProvider {
+            addr,
+            state,
+            accept_thread: Some(accept_thread),
+        })
+    }
+
+    /// Load all blocks from a CAR stream, e.g. a fixture included via `include_bytes!`.
+    ///
+    /// Returns the roots listed in the CAR header.
+    ///
+    /// # Errors
+    ///
+    /// This function returns `Err` when the CAR stream is malformed or a block does not match its
+    /// CID.
+    pub fn add_car(&self, car: impl Read) -> Result<Vec<Cid>, CarError> {
+        let reader = CarReader::new(car)?;
+        let roots = reader.roots().to_vec();
+        for block in reader {
+            let (cid, data) = block?;
+            self.add_block(cid, data);
+        }
+        Ok(roots)
+    }
+
+    /// Add a single block. The data is not verified against the CID, which allows tests to
+    /// simulate misbehaving providers.
+    pub fn add_block(&self, cid: Cid, data: Vec<u8>) {
+        lock(&self.state.blocks).insert(cid, data);
+    }
+
+    /// Add a file split into raw chunks of `chunk_size` bytes linked from a single dag-pb
+    /// root node. Returns the CID of the file.
+    ///
+    /// Data that fits into a single chunk is stored as a single raw block.
+    ///
+    /// # Panics
+    ///
+    /// Panics when `chunk_size` is zero.
+    #[must_use]
+    pub fn add_file(&self, data: &[u8], chunk_size: usize) -> Cid {
+        assert!(chunk_size > 0, "chunk size must be positive");
+        if data.len() <= chunk_size {
+            let cid = block_cid(RAW, data);
+            self.add_block(cid, data.to_vec());
+            return cid;
+        }
+
+        let mut node = Vec::new();
+        let mut unixfs = Vec::new();
+        write_varint_field(&mut unixfs, 1, TYPE_FILE);
+        write_varint_field(&mut unixfs, 3, data.len() as u64);
+        for chunk in data.chunks(chunk_size) {
+            let cid = block_cid(RAW, chunk);
+            self.add_block(cid, chunk.to_vec());
+
+            let mut link = Vec::new();
+            write_bytes_field(&mut link, 1, &cid.to_bytes());
+            write_bytes_field(&mut link, 2, b"");
+            write_varint_field(&mut link, 3, chunk.len() as u64);
+            write_bytes_field(&mut node, 2, &link);
+
+            write_varint_field(&mut unixfs, 4, chunk.len() as u64);
+        }
+        write_bytes_field(&mut node, 1, &unixfs);
+
+        let cid = block_cid(DAG_PB, &node);
+        self.add_block(cid, node);
+        cid
+    }
+
+    /// Wait for `delay` before sending each block, e.g. to trigger retrieval timeouts.
+    pub fn set_block_delay(&self, delay: Duration) {
+        *lock(&self.state.block_delay) = delay;
+    }
+
+    /// The address where the provider is listening.
+    #[must_use]
+    pub fn addr(&self) -> SocketAddr {
+        self.addr
+    }
+
+    /// The provider description to use in [`DaemonConfig::providers`] or
+    /// [`FetchOptions::providers`](crate::FetchOptions::providers).
+    #[must_use]
+    pub fn provider(&self) -> Provider {
+        Provider::new(format!(
+            "/ip4/{}/tcp/{}/http/p2p/{PEER_ID}",
+            self.addr.ip(),
+            self.addr.port()
+        ))
+        .with_protocol(Protocol::Http)
+    }
+
+    /// Daemon configuration pinned to this provider and the HTTP protocol, so that Lassie does
+    /// not contact the network indexer or other peers.
+    #[must_use]
+    pub fn daemon_config(&self) -> DaemonConfig {
+        DaemonConfig {
+            providers: vec![self.provider()],
+            protocols: Some([Protocol::Http].into()),
+            ..DaemonConfig::default()
+        }
+    }
+
+    /// Start a daemon using [`TestProvider::daemon_config`].
+    ///
+    /// # Errors
+    ///
+    /// See [`Daemon::start`].
+    pub fn start_daemon(&self) -> Result<Daemon, StartError> {
+        Daemon::start(self.daemon_config())
+    }
+}
+
+impl Drop for TestProvider {
+    fn drop(&mut self) {
+        self.state.stopped.store(true, Ordering::SeqCst);
+        // Wake up the accept loop so that it can notice the flag
+        let _ = TcpStream::connect(self.addr);
+        if let Some(thread) = self.accept_thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+fn accept_connections(listener: &TcpListener, state: &Arc<ProviderState>) {
+    for stream in listener.incoming() {
+        if state.stopped.load(Ordering::SeqCst) {
+            break;
+        }
+        let stream = match stream {
+            Ok(stream) => stream,
+            Err(err) => {
+                log::warn!("Test provider cannot accept a connection: {err}");
+                continue;
+            }
+        };
+        let state = state.clone();
+        std::thread::spawn(move || {
+            if let Err(err) = handle_connection(stream, &state) {
+                log::debug!("Test provider connection failed: {err}");
+            }
+        });
+    }
+}
+
+struct GatewayRequest {
+    method: String,
+    target: String,
+    accept: String,
+}
+
+fn handle_connection(stream: TcpStream, state: &ProviderState) -> std::io::Result<()> {
+    let mut reader = BufReader::new(stream.try_clone()?);
+    let mut writer = stream;
+
+    let Some(request) = read_request(&mut reader)? else {
+        return Ok(());
+    };
+    log::debug!(
+        "Test provider request: {} {}",
+        request.method,
+        request.target
+    );
+
+    if request.method != "GET" {
+        return write_error(
+            &mut writer,
+            "405 Method Not Allowed",
+            "only GET is supported",
+        );
+    }
+
+    let (path, query) = request
+        .target
+        .split_once('?')
+        .unwrap_or((&request.target, ""));
+    let Some(path) = path.strip_prefix("/ipfs/") else {
+        return write_error(
+            &mut writer,
+            "404 Not Found",
+            "only /ipfs/ paths are supported",
+        );
+    };
+    let mut segments = path.split('/').filter(|s| !s.is_empty());
+    let Some(root) = segments.next().and_then(|s| Cid::try_from(s).ok()) else {
+        return write_error(&mut writer, "400 Bad Request", "invalid root CID");
+    };
+
+    let params = query
+        .split('&')
+        .filter_map(|param| param.split_once('='))
+        .collect::<HashMap<_, _>>();
+    let scope = params.get("dag-scope").copied().unwrap_or("all");
+    let dups = match params.get("dups") {
+        Some(value) => *value == "y",
+        None => request.accept.contains("dups=y"),
+    };
+
+    // Resolve the path before sending the response headers, so that we can report 404
+    let mut path_cids = vec![root];
+    for name in segments {
+        let parent = path_cids[path_cids.len() - 1];
+        let Some(child) = resolve_link(state, &parent, name) else {
+            return write_error(&mut writer, "404 Not Found", "path not found");
+        };
+        path_cids.push(child);
+    }
+    let terminal = path_cids[path_cids.len() - 1];
+    if !lock(&state.blocks).contains_key(&terminal) {
+        return write_error(&mut writer, "404 Not Found", "block not found");
+    }
+
+    write!(
+        writer,
+        "HTTP/1.1 200 OK\r\n\
+         Content-Type: application/vnd.ipld.car;version=1;order=dfs;dups={}\r\n\
+         Connection: close\r\n\r\n",
+        if dups { "y" } else { "n" }
+    )?;
+
+    let mut car = CarWriter {
+        out: writer,
+        state,
+        dups,
+        seen: HashSet::new(),
+    };
+    car.write_header(&root)?;
+    for cid in &path_cids[..path_cids.len() - 1] {
+        car.write_block(cid)?;
+    }
+    car.write_dag(&terminal, scope, 0)?;
+    car.out.flush()?;
+    car.out.shutdown(Shutdown::Write)
+}
+
+fn read_request(reader: &mut impl BufRead) -> std::io::Result<Option<GatewayRequest>> {
+    let mut line = String::new();
+    if reader.read_line(&mut line)? == 0 {
+        return Ok(None);
+    }
+    let mut parts = line.split_whitespace();
+    let method = parts.next().unwrap_or_default().to_string();
+    let target = parts.next().unwrap_or_default().to_string();
+
+    let mut accept = String::new();
+    loop {
+        line.clear();
+        if reader.read_line(&mut line)? == 0 || line.trim().is_empty() {
+            break;
+        }
+        if let Some((name, value)) = line.split_once(':') {
+            if name.trim().eq_ignore_ascii_case("accept") {
+                accept = value.trim().to_string();
+            }
+        }
+    }
+
+    Ok(Some(GatewayRequest {
+        method,
+        target,
+        accept,
+    }))
+}
+
+fn write_error(writer: &mut impl Write, status: &str, message: &str) -> std::io::Result<()> {
+    write!(
+        writer,
+        "HTTP/1.1 {status}\r\nContent-Type: text/plain\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{message}",
+        message.len()
+    )
+}
+
+fn resolve_link(state: &ProviderState, parent: &Cid, name: &str) -> Option<Cid> {
+    if parent.codec() != DAG_PB {
+        return None;
+    }
+    let block = lock(&state.blocks).get(parent)?.clone();
+    let node = unixfs::decode_pb_node(&block).ok()?;
+    node.links
+        .into_iter()
+        .find(|link| link.name == name)
+        .map(|link| link.cid)
+}
+
+struct CarWriter<'a> {
+    out: TcpStream,
+    state: &'a ProviderState,
+    dups: bool,
+    seen: HashSet<Cid>,
+}
+
+impl CarWriter<'_> {
+    fn write_header(&mut self, root: &Cid) -> std::io::Result<()> {
+        self.out.write_all(&car_header(root))
+    }
+
+    /// Write the block and return its data, or `None` when the provider does not have the block.
+    fn write_block(&mut self, cid: &Cid) -> std::io::Result<Option<Vec<u8>>> {
+        let Some(data) = lock(&self.state.blocks).get(cid).cloned() else {
+            return Ok(None);
+        };
+
+        let delay = *lock(&self.state.block_delay);
+        if !delay.is_zero() {
+            std::thread::sleep(delay);
+        }
+
+        self.out.write_all(&car_block(cid, &data))?;
+        Ok(Some(data))
+    }
+
+    fn write_dag(&mut self, cid: &Cid, scope: &str, depth: usize) -> std::io::Result<()> {
+        if !self.dups && !self.seen.insert(*cid) {
+            return Ok(());
+        }
+
+        // Stop at missing blocks, Lassie reports the incomplete response as a failure
+        let Some(data) = self.write_block(cid)? else {
+            return Ok(());
+        };
+        if scope == "block" || cid.codec() != DAG_PB {
+            return Ok(());
+        }
+
+        let Ok(node) = unixfs::decode_pb_node(&data) else {
+            return Ok(());
+        };
+        if scope == "entity" && depth == 0 {
+            let node_type = unixfs::decode_unixfs_data(&node.data).map(|d| d.node_type);
+            if matches!(node_type, Ok(TYPE_DIRECTORY | TYPE_HAMT_SHARD)) {
+                return Ok(());
+            }
+        }
+        for link in node.links {
+            self.write_dag(&link.cid, "all", depth + 1)?;
+        }
+        Ok(())
+    }
+}
+
+fn lock<T>(mutex: &Mutex<T>) -> std::sync::MutexGuard<'_, T> {
+    // The state stays consistent even if a connection thread panicked
+    mutex
+        .lock()
+        .unwrap_or_else(std::sync::PoisonError::into_inner)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    const FIXTURE: &[u8] = include_bytes!(
+        "../tests/testdata/bafkreih25dih6ug3xtj73vswccw423b56ilrwmnos4cbwhrceudopdp5sq.car"
+    );
+
+    #[test]
+    fn serves_car_fixture() {
+        let provider = TestProvider::start().unwrap();
+        let roots = provider.add_car(FIXTURE).unwrap();
+
+        let car = get(&provider, &format!("/ipfs/{}?dag-scope=all", roots[0]));
+        assert_eq!(car, FIXTURE);
+    }
+
+    #[test]
+    fn serves_chunked_file_with_block_scope() {
+        let provider = TestProvider::start().unwrap();
+        let data = (0..251u8).cycle().take(5000).collect::<Vec<_>>();
+        let cid = provider.add_file(&data, 1024);
+
+        let blocks = CarReader::new(get(&provider, &format!("/ipfs/{cid}")).as_slice())
+            .unwrap()
+            .map(|block| block.unwrap().0)
+            .collect::<Vec<_>>();
+        assert_eq!(blocks.len(), 6, "root node and five chunks");
+        assert_eq!(blocks[0], cid);
+
+        let blocks =
+            CarReader::new(get(&provider, &format!("/ipfs/{cid}?dag-scope=block")).as_slice())
+                .unwrap()
+                .count();
+        assert_eq!(blocks, 1);
+    }
+
+    #[test]
+    fn reports_missing_root() {
+        let provider = TestProvider::start().unwrap();
+        let response = std::str::from_utf8(&request(
+            &provider,
+            "/ipfs/bafkreih25dih6ug3xtj73vswccw423b56ilrwmnos4cbwhrceudopdp5sq",
+        ))
+        .unwrap()
+        .to_string();
+        assert!(
+            response.starts_with("HTTP/1.1 404"),
+            "unexpected response: {response}"
+        );
+    }
+
+    fn get(provider: &TestProvider, target: &str) -> Vec<u8> {
+        let response = request(provider, target);
+        let body_start = response
+            .windows(4)
+            .position(|w| w == b"\r\n\r\n")
+            .expect("response should have headers")
+            + 4;
+        assert!(response.starts_with(b"HTTP/1.1 200"));
+        response[body_start..].to_vec()
+    }
+
+    fn request(provider: &TestProvider, target: &str) -> Vec<u8> {
+        let mut stream = TcpStream::connect(provider.addr()).unwrap();
+        write!(stream, "GET {target} HTTP/1.1\r\nHost: localhost\r\n\r\n").unwrap();
+        let mut response = Vec::new();
+        stream.read_to_end(&mut response).unwrap();
+        response
+    }
+}
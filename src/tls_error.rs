@@ -0,0 +1,68 @@
+use std::ffi::c_int;
+use std::fmt::{Display, Formatter};
+use std::path::PathBuf;
+
+// The error kinds reported by Go, must be kept in sync with errorKind* constants in
+// go-lib/lassie-ffi.go
+const ERROR_KIND_TLS_CERTIFICATE: c_int = 3;
+const ERROR_KIND_TLS_KEY: c_int = 4;
+const ERROR_KIND_TLS_KEY_MISMATCH: c_int = 5;
+
+#[derive(Debug, PartialEq, Clone)]
+#[non_exhaustive]
+pub enum TlsError {
+    /// The certificate file cannot be read.
+    CannotReadCertificate {
+        path: PathBuf,
+        reason: String,
+    },
+    /// The private key file cannot be read.
+    CannotReadKey {
+        path: PathBuf,
+        reason: String,
+    },
+    /// The certificate chain is not valid PEM-encoded X.509 data.
+    InvalidCertificate(String),
+    /// The private key is not a valid PEM-encoded PKCS #1, PKCS #8 or SEC 1 key.
+    InvalidKey(String),
+    /// The private key does not belong to the certificate.
+    KeyMismatch(String),
+    /// The daemon was started without TLS, there is no certificate to reload.
+    NotEnabled,
+    Lassie(String),
+}
+
+impl TlsError {
+    /// Returns `None` when the error kind does not describe a TLS problem.
+    pub(crate) fn from_go(kind: c_int, msg: String) -> Option<Self> {
+        match kind {
+            ERROR_KIND_TLS_CERTIFICATE => Some(TlsError::InvalidCertificate(msg)),
+            ERROR_KIND_TLS_KEY => Some(TlsError::InvalidKey(msg)),
+            ERROR_KIND_TLS_KEY_MISMATCH => Some(TlsError::KeyMismatch(msg)),
+            _ => None,
+        }
+    }
+}
+
+impl Display for TlsError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+        write!(f, "invalid TLS configuration: ")?;
+        match self {
+            TlsError::CannotReadCertificate { path, reason } => f.write_fmt(format_args!(
+                "cannot read the certificate file {:?}: {reason}",
+                path.display(),
+            )),
+            TlsError::CannotReadKey { path, reason } => f.write_fmt(format_args!(
+                "cannot read the private key file {:?}: {reason}",
+                path.display(),
+            )),
+            TlsError::InvalidCertificate(msg)
+            | TlsError::InvalidKey(msg)
+            | TlsError::KeyMismatch(msg)
+            | TlsError::Lassie(msg) => f.write_str(msg),
+            TlsError::NotEnabled => f.write_str("the daemon was started without TLS"),
+        }
+    }
+}
+
+impl std::error::Error for TlsError {}
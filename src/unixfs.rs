@@ -0,0 +1,735 @@
+//! Extraction of [UnixFS](https://specs.ipfs.tech/unixfs/) files and directories from CAR
+//! streams.
+//!
+//! The extractor supports files chunked into multiple blocks, directories and HAMT-sharded
+//! directories. Directory entries are never written outside the destination: entry names that are
+//! empty, `.`, `..` or contain path separators are rejected, and existing files are never
+//! overwritten.
+//!
+//! The CAR stream must include duplicate blocks (`dups=y`), which is the default of the `/ipfs/`
+//! endpoint and of [`Daemon::fetch_to_path`](crate::Daemon::fetch_to_path).
+
+use std::collections::HashMap;
+use std::fs::{self, File, OpenOptions};
+use std::io::{Read, Write};
+use std::path::{Component, Path, PathBuf};
+
+use cid::Cid;
+
+use crate::car::CarReader;
+pub use crate::unixfs_error::UnixfsError;
+
+// Codes from https://github.com/multiformats/multicodec/blob/master/table.csv
+pub(crate) const DAG_PB: u64 = 0x70;
+pub(crate) const RAW: u64 = 0x55;
+const IDENTITY: u64 = 0x00;
+
+// UnixFS node types, see https://specs.ipfs.tech/unixfs/#data-types
+const TYPE_RAW: u64 = 0;
+pub(crate) const TYPE_DIRECTORY: u64 = 1;
+pub(crate) const TYPE_FILE: u64 = 2;
+const TYPE_SYMLINK: u64 = 4;
+pub(crate) const TYPE_HAMT_SHARD: u64 = 5;
+
+/// Protects the stack from malicious DAGs. Real-world directory trees are much shallower.
+const MAX_DEPTH: usize = 256;
+
+/// Progress of the extraction reported to the callback of [`extract_with_progress`].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ExtractProgress {
+    /// Number of files created so far.
+    pub files: u64,
+    /// Number of directories created so far.
+    pub directories: u64,
+    /// Number of bytes written to files so far.
+    pub bytes: u64,
+    /// The file or directory being extracted, relative to the destination.
+    pub current: PathBuf,
+}
+
+/// Extract the file or directory at the root of the CAR stream to `dest`.
+///
+/// When the root is a file, `dest` is the path of the file. When the root is a directory, `dest`
+/// is the directory where the entries are extracted to.
+///
+/// # Errors
+///
+/// This function returns `Err` when the CAR stream is malformed, does not encode a file or
+/// directory, an entry name is not safe or a file cannot be written.
+pub fn extract<R: Read>(
+    car: CarReader<R>,
+    dest: impl AsRef<Path>,
+) -> Result<ExtractProgress, UnixfsError> {
+    extract_with_progress(car, dest, |_| {})
+}
+
+/// Same as [`extract`], calling `on_progress` whenever a file or directory is created and
+/// whenever a chunk of data is written.
+///
+/// # Errors
+///
+/// See [`extract`].
+pub fn extract_with_progress<R: Read>(
+    car: CarReader<R>,
+    dest: impl AsRef<Path>,
+    mut on_progress: impl FnMut(&ExtractProgress),
+) -> Result<ExtractProgress, UnixfsError> {
+    run_extractor(car, "", dest.as_ref(), &mut on_progress)
+}
+
+/// Extract the file or directory at `path` within the DAG at the root of the CAR stream, e.g. a
+/// stream retrieved with [`FetchOptions::path`](crate::FetchOptions::path) set to the same path.
+///
+/// Only plain directories can be traversed, paths through HAMT-sharded directories are not
+/// supported.
+///
+/// # Errors
+///
+/// See [`extract`]. Additionally, this function returns [`UnixfsError::PathNotFound`] when the
+/// path does not exist in the DAG.
+pub fn extract_path<R: Read>(
+    car: CarReader<R>,
+    path: &str,
+    dest: impl AsRef<Path>,
+) -> Result<ExtractProgress, UnixfsError> {
+    run_extractor(car, path, dest.as_ref(), &mut |_| {})
+}
+
+fn run_extractor<R: Read>(
+    car: CarReader<R>,
+    path: &str,
+    dest: &Path,
+    on_progress: &mut dyn FnMut(&ExtractProgress),
+) -> Result<ExtractProgress, UnixfsError> {
+    let root = *car.roots().first().ok_or(UnixfsError::NoRoot)?;
+    let mut extractor = Extractor {
+        car,
+        pending: HashMap::new(),
+        dest: dest.to_path_buf(),
+        progress: ExtractProgress::default(),
+        on_progress,
+    };
+    let terminal = extractor.resolve_path(root, path)?;
+    extractor.extract_entry(&terminal, PathBuf::new(), 0)?;
+    Ok(extractor.progress)
+}
+
+struct Extractor<'a, R: Read> {
+    car: CarReader<R>,
+    // Blocks received before they were needed
+    pending: HashMap<Cid, Vec<u8>>,
+    dest: PathBuf,
+    progress: ExtractProgress,
+    on_progress: &'a mut dyn FnMut(&ExtractProgress),
+}
+
+impl<R: Read> Extractor<'_, R> {
+    fn resolve_path(&mut self, root: Cid, path: &str) -> Result<Cid, UnixfsError> {
+        let mut cid = root;
+        for name in path.split('/').filter(|s| !s.is_empty()) {
+            let Node::Pb(node, unixfs) = self.load_node(&cid)? else {
+                return Err(UnixfsError::PathNotFound(path.to_string()));
+            };
+            match unixfs.node_type {
+                TYPE_DIRECTORY => {}
+                TYPE_HAMT_SHARD => {
+                    return Err(UnixfsError::UnsupportedNodeType {
+                        cid,
+                        node_type: TYPE_HAMT_SHARD,
+                    })
+                }
+                _ => return Err(UnixfsError::PathNotFound(path.to_string())),
+            }
+            cid = node
+                .links
+                .into_iter()
+                .find(|link| link.name == name)
+                .ok_or_else(|| UnixfsError::PathNotFound(path.to_string()))?
+                .cid;
+        }
+        Ok(cid)
+    }
+
+    fn extract_entry(&mut self, cid: &Cid, rel: PathBuf, depth: usize) -> Result<(), UnixfsError> {
+        if depth > MAX_DEPTH {
+            return Err(UnixfsError::TooDeep);
+        }
+
+        match self.load_node(cid)? {
+            Node::Raw(data) => {
+                let mut file = self.create_file(rel)?;
+                self.write_data(&mut file, &data)
+            }
+            Node::Pb(node, unixfs) => match unixfs.node_type {
+                TYPE_FILE | TYPE_RAW => {
+                    let mut file = self.create_file(rel)?;
+                    self.write_file(&mut file, node, &unixfs.data, depth)
+                }
+                TYPE_DIRECTORY => {
+                    self.create_dir(rel.clone())?;
+                    for link in node.links {
+                        let name = validate_name(&link.name)?;
+                        self.extract_entry(&link.cid, rel.join(name), depth + 1)?;
+                    }
+                    Ok(())
+                }
+                TYPE_HAMT_SHARD => {
+                    let padding = hamt_padding(cid, unixfs.fanout)?;
+                    self.create_dir(rel.clone())?;
+                    self.extract_shard(node, &rel, padding, depth)
+                }
+                TYPE_SYMLINK => self.create_symlink(rel, &unixfs.data),
+                node_type => Err(UnixfsError::UnsupportedNodeType {
+                    cid: *cid,
+                    node_type,
+                }),
+            },
+        }
+    }
+
+    fn extract_shard(
+        &mut self,
+        shard: PbNode,
+        rel: &Path,
+        padding: usize,
+        depth: usize,
+    ) -> Result<(), UnixfsError> {
+        if depth > MAX_DEPTH {
+            return Err(UnixfsError::TooDeep);
+        }
+
+        for link in shard.links {
+            // Links to sub-shards are named with the hex prefix only, links to entries are named
+            // with the prefix followed by the entry name.
+            let Some(name) = link.name.get(padding..) else {
+                return Err(UnixfsError::InvalidNode {
+                    cid: link.cid,
+                    reason: format!("malformed HAMT link name {:?}", link.name),
+                });
+            };
+
+            if name.is_empty() {
+                let Node::Pb(node, unixfs) = self.load_node(&link.cid)? else {
+                    return Err(UnixfsError::UnsupportedNodeType {
+                        cid: link.cid,
+                        node_type: TYPE_RAW,
+                    });
+                };
+                if unixfs.node_type != TYPE_HAMT_SHARD {
+                    return Err(UnixfsError::UnsupportedNodeType {
+                        cid: link.cid,
+                        node_type: unixfs.node_type,
+                    });
+                }
+                self.extract_shard(node, rel, padding, depth + 1)?;
+            } else {
+                let name = validate_name(name)?;
+                self.extract_entry(&link.cid, rel.join(name), depth + 1)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn write_file(
+        &mut self,
+        file: &mut File,
+        node: PbNode,
+        data: &[u8],
+        depth: usize,
+    ) -> Result<(), UnixfsError> {
+        if depth > MAX_DEPTH {
+            return Err(UnixfsError::TooDeep);
+        }
+
+        // The data of the node precedes the data of its children
+        self.write_data(file, data)?;
+        for link in node.links {
+            match self.load_node(&link.cid)? {
+                Node::Raw(data) => self.write_data(file, &data)?,
+                Node::Pb(child, unixfs) if matches!(unixfs.node_type, TYPE_FILE | TYPE_RAW) => {
+                    self.write_file(file, child, &unixfs.data, depth + 1)?;
+                }
+                Node::Pb(_, unixfs) => {
+                    return Err(UnixfsError::UnsupportedNodeType {
+                        cid: link.cid,
+                        node_type: unixfs.node_type,
+                    })
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn load_node(&mut self, cid: &Cid) -> Result<Node, UnixfsError> {
+        let block = self.load_block(cid)?;
+        match cid.codec() {
+            RAW => Ok(Node::Raw(block)),
+            DAG_PB => {
+                let invalid = |reason| UnixfsError::InvalidNode { cid: *cid, reason };
+                let node = decode_pb_node(&block).map_err(invalid)?;
+                let unixfs = decode_unixfs_data(&node.data).map_err(invalid)?;
+                Ok(Node::Pb(node, unixfs))
+            }
+            codec => Err(UnixfsError::UnsupportedCodec { cid: *cid, codec }),
+        }
+    }
+
+    fn load_block(&mut self, cid: &Cid) -> Result<Vec<u8>, UnixfsError> {
+        // Identity CIDs carry the data inline and are not included in CAR streams
+        if cid.hash().code() == IDENTITY {
+            return Ok(cid.hash().digest().to_vec());
+        }
+
+        if let Some(block) = self.pending.remove(cid) {
+            return Ok(block);
+        }
+
+        // Lassie sends the blocks in the order we need them, the pending map stays empty unless the
+        // CAR stream uses a different order.
+        while let Some((block_cid, block)) = self.car.next_block()? {
+            if block_cid == *cid {
+                return Ok(block);
+            }
+            self.pending.insert(block_cid, block);
+        }
+        Err(UnixfsError::MissingBlock(*cid))
+    }
+
+    fn target(&self, rel: &Path) -> PathBuf {
+        if rel.as_os_str().is_empty() {
+            self.dest.clone()
+        } else {
+            self.dest.join(rel)
+        }
+    }
+
+    fn create_file(&mut self, rel: PathBuf) -> Result<File, UnixfsError> {
+        let path = self.target(&rel);
+        if rel.as_os_str().is_empty() {
+            if let Some(parent) = path.parent().filter(|p| !p.as_os_str().is_empty()) {
+                fs::create_dir_all(parent)?;
+            }
+        }
+
+        // Never overwrite existing files and never follow symlinks created by the extraction
+        let file = OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(&path)
+            .map_err(|err| UnixfsError::Io(format!("cannot create {}: {err}", path.display())))?;
+
+        self.progress.files += 1;
+        self.report(rel);
+        Ok(file)
+    }
+
+    fn create_dir(&mut self, rel: PathBuf) -> Result<(), UnixfsError> {
+        let path = self.target(&rel);
+        let result = if rel.as_os_str().is_empty() {
+            fs::create_dir_all(&path)
+        } else {
+            fs::create_dir(&path)
+        };
+        result
+            .map_err(|err| UnixfsError::Io(format!("cannot create {}: {err}", path.display())))?;
+
+        self.progress.directories += 1;
+        self.report(rel);
+        Ok(())
+    }
+
+    fn create_symlink(&mut self, rel: PathBuf, target: &[u8]) -> Result<(), UnixfsError> {
+        let path = self.target(&rel);
+        let target = String::from_utf8_lossy(target);
+
+        #[cfg(unix)]
+        std::os::unix::fs::symlink(target.as_ref(), &path)
+            .map_err(|err| UnixfsError::Io(format!("cannot create {}: {err}", path.display())))?;
+
+        #[cfg(not(unix))]
+        log::warn!(
+            "Skipping symlink {} -> {target}, symlinks are supported on Unix only",
+            path.display()
+        );
+
+        self.report(rel);
+        Ok(())
+    }
+
+    fn write_data(&mut self, file: &mut File, data: &[u8]) -> Result<(), UnixfsError> {
+        if data.is_empty() {
+            return Ok(());
+        }
+        file.write_all(data)?;
+        self.progress.bytes += data.len() as u64;
+        (self.on_progress)(&self.progress);
+        Ok(())
+    }
+
+    fn report(&mut self, current: PathBuf) {
+        self.progress.current = current;
+        (self.on_progress)(&self.progress);
+    }
+}
+
+/// Accept only names that are a single normal path component on every platform, so that joining
+/// them to the destination cannot escape it. `:` is rejected because `C:` is a drive prefix on
+/// Windows and `dest.join("C:evil")` replaces `dest`.
+fn validate_name(name: &str) -> Result<&str, UnixfsError> {
+    let mut components = Path::new(name).components();
+    let is_single_normal = matches!(
+        (components.next(), components.next()),
+        (Some(Component::Normal(component)), None) if component == name
+    );
+    if !is_single_normal || name.contains(['/', '\\', '\0', ':']) {
+        return Err(UnixfsError::InvalidName(name.to_string()));
+    }
+    Ok(name)
+}
+
+/// HAMT link names start with the index of the bucket formatted as a hex number padded to the
+/// length of `fanout - 1`.
+fn hamt_padding(cid: &Cid, fanout: u64) -> Result<usize, UnixfsError> {
+    if !fanout.is_power_of_two() || fanout < 2 {
+        return Err(UnixfsError::InvalidNode {
+            cid: *cid,
+            reason: format!("invalid HAMT fanout {fanout}"),
+        });
+    }
+    Ok(format!("{:X}", fanout - 1).len())
+}
+
+enum Node {
+    Raw(Vec<u8>),
+    Pb(PbNode, UnixfsData),
+}
+
+pub(crate) struct PbNode {
+    pub(crate) links: Vec<PbLink>,
+    pub(crate) data: Vec<u8>,
+}
+
+pub(crate) struct PbLink {
+    pub(crate) cid: Cid,
+    pub(crate) name: String,
+}
+
+pub(crate) struct UnixfsData {
+    pub(crate) node_type: u64,
+    pub(crate) data: Vec<u8>,
+    pub(crate) fanout: u64,
+}
+
+enum Field<'a> {
+    Varint(u64),
+    Bytes(&'a [u8]),
+    Other,
+}
+
+/// Decode a dag-pb node, see <https://ipld.io/specs/codecs/dag-pb/spec/>
+pub(crate) fn decode_pb_node(bytes: &[u8]) -> Result<PbNode, String> {
+    let mut node = PbNode {
+        links: Vec::new(),
+        data: Vec::new(),
+    };
+    for_each_field(bytes, |number, field| {
+        match (number, field) {
+            (1, Field::Bytes(data)) => node.data = data.to_vec(),
+            (2, Field::Bytes(link)) => node.links.push(decode_pb_link(link)?),
+            _ => return Err(format!("unexpected PBNode field {number}")),
+        }
+        Ok(())
+    })?;
+    Ok(node)
+}
+
+fn decode_pb_link(bytes: &[u8]) -> Result<PbLink, String> {
+    let mut cid = None;
+    let mut name = String::new();
+    for_each_field(bytes, |number, field| {
+        match (number, field) {
+            (1, Field::Bytes(hash)) => {
+                cid = Some(Cid::try_from(hash).map_err(|err| format!("invalid link: {err}"))?);
+            }
+            (2, Field::Bytes(value)) => {
+                name = String::from_utf8(value.to_vec())
+                    .map_err(|_| "link name is not valid UTF-8".to_string())?;
+            }
+            (3, Field::Varint(_)) => {}
+            _ => return Err(format!("unexpected PBLink field {number}")),
+        }
+        Ok(())
+    })?;
+    Ok(PbLink {
+        cid: cid.ok_or("link without hash")?,
+        name,
+    })
+}
+
+/// Decode the `Data` protobuf message stored in the data of dag-pb nodes.
+pub(crate) fn decode_unixfs_data(bytes: &[u8]) -> Result<UnixfsData, String> {
+    let mut node_type = None;
+    let mut data = Vec::new();
+    let mut fanout = 0;
+    for_each_field(bytes, |number, field| {
+        match (number, field) {
+            (1, Field::Varint(value)) => node_type = Some(value),
+            (2, Field::Bytes(value)) => data = value.to_vec(),
+            (6, Field::Varint(value)) => fanout = value,
+            // Other fields (file size, block sizes, hash type, mode, mtime) are not needed
+            _ => {}
+        }
+        Ok(())
+    })?;
+    Ok(UnixfsData {
+        node_type: node_type.ok_or("missing UnixFS node type")?,
+        data,
+        fanout,
+    })
+}
+
+fn for_each_field<'a>(
+    mut input: &'a [u8],
+    mut on_field: impl FnMut(u64, Field<'a>) -> Result<(), String>,
+) -> Result<(), String> {
+    while !input.is_empty() {
+        let key = read_varint(&mut input)?;
+        let field = match key & 0x07 {
+            0 => Field::Varint(read_varint(&mut input)?),
+            1 => {
+                take(&mut input, 8)?;
+                Field::Other
+            }
+            2 => {
+                let len = usize::try_from(read_varint(&mut input)?)
+                    .map_err(|_| "field is too long".to_string())?;
+                Field::Bytes(take(&mut input, len)?)
+            }
+            5 => {
+                take(&mut input, 4)?;
+                Field::Other
+            }
+            wire_type => return Err(format!("unsupported protobuf wire type {wire_type}")),
+        };
+        on_field(key >> 3, field)?;
+    }
+    Ok(())
+}
+
+fn read_varint(input: &mut &[u8]) -> Result<u64, String> {
+    let mut value: u64 = 0;
+    for ix in 0..10 {
+        let byte = take(input, 1)?[0];
+        value |= u64::from(byte & 0x7f) << (7 * ix);
+        if byte & 0x80 == 0 {
+            return Ok(value);
+        }
+    }
+    Err("varint is too long".to_string())
+}
+
+fn take<'a>(input: &mut &'a [u8], len: usize) -> Result<&'a [u8], String> {
+    if input.len() < len {
+        return Err("unexpected end of the node".to_string());
+    }
+    let (head, tail) = input.split_at(len);
+    *input = tail;
+    Ok(head)
+}
+
+/// Encoders building DAG-PB nodes for the test provider and the unit tests.
+#[cfg(any(test, feature = "testing"))]
+pub(crate) mod encode {
+    use crate::car::encode::write_varint;
+
+    /// Encode a protobuf varint field.
+    pub(crate) fn write_varint_field(out: &mut Vec<u8>, number: u64, value: u64) {
+        write_varint(out, number << 3);
+        write_varint(out, value);
+    }
+
+    /// Encode a protobuf length-delimited field.
+    pub(crate) fn write_bytes_field(out: &mut Vec<u8>, number: u64, value: &[u8]) {
+        write_varint(out, (number << 3) | 2);
+        write_varint(out, value.len() as u64);
+        out.extend_from_slice(value);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::encode::{write_bytes_field, write_varint_field};
+    use super::*;
+    use crate::car::encode::{block_cid, car_block, car_header};
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn extracts_directory_with_chunked_file() {
+        let chunk1 = raw_block(b"hello ");
+        let chunk2 = raw_block(b"world");
+        let file = pb_block(&[(&chunk1.0, ""), (&chunk2.0, "")], &unixfs(TYPE_FILE, 0));
+        let nested = raw_block(b"nested");
+        let subdir = pb_block(&[(&nested.0, "nested.txt")], &unixfs(TYPE_DIRECTORY, 0));
+        let root = pb_block(
+            &[(&file.0, "hello.txt"), (&subdir.0, "sub")],
+            &unixfs(TYPE_DIRECTORY, 0),
+        );
+        let car = car(&root.0, &[&root, &file, &chunk1, &chunk2, &subdir, &nested]);
+
+        let dir = temp_dir("directory");
+        let dest = dir.path();
+        let mut reports = 0;
+        let progress = extract_with_progress(CarReader::new(car.as_slice()).unwrap(), dest, |_| {
+            reports += 1;
+        })
+        .expect("cannot extract the directory");
+
+        assert_eq!(
+            fs::read_to_string(dest.join("hello.txt")).unwrap(),
+            "hello world"
+        );
+        assert_eq!(
+            fs::read_to_string(dest.join("sub").join("nested.txt")).unwrap(),
+            "nested"
+        );
+        assert_eq!(progress.files, 2);
+        assert_eq!(progress.directories, 2);
+        assert_eq!(progress.bytes, 17);
+        assert!(reports > 0, "progress should have been reported");
+    }
+
+    #[test]
+    fn extracts_path_within_directory() {
+        let nested = raw_block(b"nested");
+        let subdir = pb_block(&[(&nested.0, "nested.txt")], &unixfs(TYPE_DIRECTORY, 0));
+        let root = pb_block(&[(&subdir.0, "sub")], &unixfs(TYPE_DIRECTORY, 0));
+        let car = car(&root.0, &[&root, &subdir, &nested]);
+
+        let dir = temp_dir("path");
+        let dest = dir.path().join("nested.txt");
+        let progress = extract_path(
+            CarReader::new(car.as_slice()).unwrap(),
+            "sub/nested.txt",
+            &dest,
+        )
+        .expect("cannot extract the path");
+
+        assert_eq!(fs::read_to_string(&dest).unwrap(), "nested");
+        assert_eq!(progress.files, 1);
+
+        let missing = temp_dir("missing-path");
+        let result = extract_path(
+            CarReader::new(car.as_slice()).unwrap(),
+            "missing",
+            missing.path(),
+        );
+        assert_eq!(
+            result,
+            Err(UnixfsError::PathNotFound("missing".to_string()))
+        );
+    }
+
+    #[test]
+    fn extracts_hamt_sharded_directory() {
+        let a = raw_block(b"a");
+        let b = raw_block(b"b");
+        // Fanout 256 means two hex characters of padding
+        let sub_shard = pb_block(&[(&b.0, "0Bb.txt")], &unixfs(TYPE_HAMT_SHARD, 256));
+        let root = pb_block(
+            &[(&a.0, "0Aa.txt"), (&sub_shard.0, "F1")],
+            &unixfs(TYPE_HAMT_SHARD, 256),
+        );
+        let car = car(&root.0, &[&root, &a, &sub_shard, &b]);
+
+        let dir = temp_dir("hamt");
+        let dest = dir.path();
+        extract(CarReader::new(car.as_slice()).unwrap(), dest)
+            .expect("cannot extract the sharded directory");
+
+        assert_eq!(fs::read_to_string(dest.join("a.txt")).unwrap(), "a");
+        assert_eq!(fs::read_to_string(dest.join("b.txt")).unwrap(), "b");
+    }
+
+    #[test]
+    fn rejects_entries_escaping_destination() {
+        let evil = raw_block(b"evil");
+        let root = pb_block(&[(&evil.0, "..")], &unixfs(TYPE_DIRECTORY, 0));
+        let car = car(&root.0, &[&root, &evil]);
+
+        let dir = temp_dir("traversal");
+        let dest = dir.path();
+        let result = extract(CarReader::new(car.as_slice()).unwrap(), dest);
+        assert_eq!(result, Err(UnixfsError::InvalidName("..".to_string())));
+    }
+
+    #[test]
+    fn rejects_entries_with_drive_prefix() {
+        let evil = raw_block(b"evil");
+        let root = pb_block(&[(&evil.0, "C:evil")], &unixfs(TYPE_DIRECTORY, 0));
+        let car = car(&root.0, &[&root, &evil]);
+
+        let dir = temp_dir("drive-prefix");
+        let dest = dir.path();
+        let result = extract(CarReader::new(car.as_slice()).unwrap(), dest);
+        assert_eq!(result, Err(UnixfsError::InvalidName("C:evil".to_string())));
+        assert_eq!(
+            validate_name("C:"),
+            Err(UnixfsError::InvalidName("C:".to_string()))
+        );
+    }
+
+    fn raw_block(data: &[u8]) -> (Cid, Vec<u8>) {
+        (block_cid(RAW, data), data.to_vec())
+    }
+
+    fn pb_block(links: &[(&Cid, &str)], data: &[u8]) -> (Cid, Vec<u8>) {
+        let mut node = Vec::new();
+        for (cid, name) in links {
+            let mut link = Vec::new();
+            write_bytes_field(&mut link, 1, &cid.to_bytes());
+            write_bytes_field(&mut link, 2, name.as_bytes());
+            write_bytes_field(&mut node, 2, &link);
+        }
+        write_bytes_field(&mut node, 1, data);
+        (block_cid(DAG_PB, &node), node)
+    }
+
+    fn unixfs(node_type: u64, fanout: u64) -> Vec<u8> {
+        let mut data = Vec::new();
+        write_varint_field(&mut data, 1, node_type);
+        if fanout > 0 {
+            write_varint_field(&mut data, 6, fanout);
+        }
+        data
+    }
+
+    fn car(root: &Cid, blocks: &[&(Cid, Vec<u8>)]) -> Vec<u8> {
+        let mut car = car_header(root);
+        for (cid, data) in blocks {
+            car.extend_from_slice(&car_block(cid, data));
+        }
+        car
+    }
+
+    /// A directory for the files extracted by a test, removed when the test finishes.
+    struct TempDir(PathBuf);
+
+    impl TempDir {
+        fn path(&self) -> &Path {
+            &self.0
+        }
+    }
+
+    impl Drop for TempDir {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.0);
+        }
+    }
+
+    fn temp_dir(name: &str) -> TempDir {
+        let dir =
+            std::env::temp_dir().join(format!("rusty-lassie-unixfs-{name}-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        TempDir(dir)
+    }
+}
@@ -0,0 +1,75 @@
+use std::fmt::{Display, Formatter};
+
+use cid::Cid;
+
+use crate::car::CarError;
+
+#[derive(Debug, PartialEq, Clone)]
+#[non_exhaustive]
+pub enum UnixfsError {
+    /// The CAR stream is malformed or a block does not match its CID.
+    Car(CarError),
+    /// Writing to the destination failed.
+    Io(String),
+    /// The CAR header does not list any root.
+    NoRoot,
+    /// A block of the DAG is not present in the CAR stream.
+    MissingBlock(Cid),
+    /// The block is not a valid dag-pb node or its data cannot be decoded.
+    InvalidNode { cid: Cid, reason: String },
+    /// The block uses a codec other than dag-pb or raw.
+    UnsupportedCodec { cid: Cid, codec: u64 },
+    /// The node type cannot be extracted (e.g. metadata), or it cannot appear at this place
+    /// of the DAG (e.g. a directory inside a file).
+    UnsupportedNodeType { cid: Cid, node_type: u64 },
+    /// A directory entry name would escape the destination directory or is not a valid file name.
+    InvalidName(String),
+    /// The path does not exist in the DAG.
+    PathNotFound(String),
+    /// The DAG is nested deeper than we support.
+    TooDeep,
+}
+
+impl Display for UnixfsError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+        write!(f, "failed to extract UnixFS content: ")?;
+        match self {
+            UnixfsError::Car(err) => err.fmt(f),
+            UnixfsError::Io(msg) => f.write_str(msg),
+            UnixfsError::NoRoot => f.write_str("the CAR stream does not have any root"),
+            UnixfsError::MissingBlock(cid) => {
+                f.write_fmt(format_args!("block {cid} is missing in the CAR stream"))
+            }
+            UnixfsError::InvalidNode { cid, reason } => {
+                f.write_fmt(format_args!("invalid node {cid}: {reason}"))
+            }
+            UnixfsError::UnsupportedCodec { cid, codec } => f.write_fmt(format_args!(
+                "block {cid} uses unsupported codec 0x{codec:x}"
+            )),
+            UnixfsError::UnsupportedNodeType { cid, node_type } => f.write_fmt(format_args!(
+                "node {cid} has unsupported UnixFS type {node_type}"
+            )),
+            UnixfsError::InvalidName(name) => {
+                f.write_fmt(format_args!("invalid directory entry name {name:?}"))
+            }
+            UnixfsError::PathNotFound(path) => {
+                f.write_fmt(format_args!("path {path:?} not found in the DAG"))
+            }
+            UnixfsError::TooDeep => f.write_str("the DAG is nested too deeply"),
+        }
+    }
+}
+
+impl std::error::Error for UnixfsError {}
+
+impl From<CarError> for UnixfsError {
+    fn from(err: CarError) -> Self {
+        UnixfsError::Car(err)
+    }
+}
+
+impl From<std::io::Error> for UnixfsError {
+    fn from(err: std::io::Error) -> Self {
+        UnixfsError::Io(err.to_string())
+    }
+}
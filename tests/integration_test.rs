@@ -1,16 +1,17 @@
 use pretty_assertions::assert_eq;
-use std::sync::{Mutex, MutexGuard};
+use std::io::Read;
+use std::sync::mpsc::RecvTimeoutError;
 use std::time::Duration;
 
-use lassie::{Daemon, DaemonConfig};
-
-// Rust runs tests in parallel. Since Lassie Daemon is a singleton,
-// we must synchronise the tests to ensure they run sequentially
-static TEST_GUARD: Mutex<()> = Mutex::new(());
+use lassie::car::CarReader;
+use lassie::testing::TestProvider;
+use lassie::{
+    AccessToken, AccessTokenPolicy, Cid, Daemon, DaemonConfig, FetchOptions, RetrievalEventKind,
+};
 
 #[test]
 fn start_daemon_and_request_cid() {
-    let _lock = setup_test_env();
+    setup_test_env();
 
     let daemon = Daemon::start(DaemonConfig::default()).expect("cannot start Lassie");
     let port = daemon.port();
@@ -46,22 +47,206 @@ fn start_daemon_and_request_cid() {
     );
 }
 
+#[cfg(feature = "client")]
+#[test]
+fn start_daemon_and_request_cid_with_client() {
+    use lassie::client::{CarOrder, Client};
+
+    setup_test_env();
+
+    let daemon = Daemon::start(DaemonConfig {
+        access_tokens: vec![AccessToken::new("super_secret")],
+        ..DaemonConfig::default()
+    })
+    .expect("cannot start Lassie");
+
+    let cid: Cid = "bafkreih25dih6ug3xtj73vswccw423b56ilrwmnos4cbwhrceudopdp5sq"
+        .parse()
+        .unwrap();
+    let mut response = Client::new(&daemon)
+        .request(cid)
+        .car_order(CarOrder::Dfs)
+        .dups(true)
+        .send()
+        .expect("cannot request CID using the client");
+
+    let content_type = response
+        .headers()
+        .content_type
+        .clone()
+        .expect("response should have CAR content type");
+    assert_eq!(content_type.order, Some(CarOrder::Dfs));
+    assert_eq!(content_type.dups, Some(true));
+
+    let mut content = Vec::new();
+    response
+        .read_to_end(&mut content)
+        .expect("cannot read response body");
+    assert_eq!(
+        content,
+        include_bytes!("testdata/bafkreih25dih6ug3xtj73vswccw423b56ilrwmnos4cbwhrceudopdp5sq.car")
+    );
+}
+
+#[test]
+fn start_daemon_and_fetch_cid_in_process() {
+    setup_test_env();
+
+    let (provider, cid) = start_fixture_provider();
+    let daemon = provider.start_daemon().expect("cannot start Lassie");
+
+    let mut content = Vec::new();
+    daemon
+        .fetch(cid, FetchOptions::default())
+        .read_to_end(&mut content)
+        .expect("cannot fetch CID using Lassie");
+
+    assert_eq!(
+        content,
+        include_bytes!("testdata/bafkreih25dih6ug3xtj73vswccw423b56ilrwmnos4cbwhrceudopdp5sq.car")
+    );
+}
+
+#[test]
+fn fetch_cid_and_verify_blocks() {
+    setup_test_env();
+
+    let (provider, cid) = start_fixture_provider();
+    let daemon = provider.start_daemon().expect("cannot start Lassie");
+
+    let car =
+        CarReader::new(daemon.fetch(cid, FetchOptions::default())).expect("cannot read CAR header");
+    assert_eq!(car.roots(), &[cid]);
+
+    let blocks = car
+        .map(|block| block.map(|(cid, _)| cid))
+        .collect::<Result<Vec<_>, _>>()
+        .expect("cannot read verified blocks");
+    assert_eq!(blocks, vec![cid]);
+}
+
+#[test]
+fn fetch_cid_to_path() {
+    setup_test_env();
+
+    let (provider, cid) = start_fixture_provider();
+    let daemon = provider.start_daemon().expect("cannot start Lassie");
+
+    let dest = std::env::temp_dir().join(format!("rusty-lassie-{}-{cid}", std::process::id()));
+    let _ = std::fs::remove_file(&dest);
+
+    let progress = daemon
+        .fetch_to_path(cid, &dest)
+        .expect("cannot extract the content");
+    assert_eq!(progress.files, 1);
+
+    let content = std::fs::read(&dest).expect("cannot read the extracted file");
+    assert_eq!(content.len() as u64, progress.bytes);
+    assert!(
+        !content.is_empty(),
+        "the extracted file should not be empty"
+    );
+    std::fs::remove_file(&dest).unwrap();
+}
+
+#[test]
+fn get_single_block() {
+    setup_test_env();
+
+    let (provider, cid) = start_fixture_provider();
+    let daemon = provider.start_daemon().expect("cannot start Lassie");
+
+    let data = daemon.get_block(cid).expect("cannot get the block");
+    assert!(!data.is_empty(), "the block data should not be empty");
+}
+
+#[test]
+fn reports_retrieval_events() {
+    setup_test_env();
+
+    let (provider, cid) = start_fixture_provider();
+    let daemon = provider.start_daemon().expect("cannot start Lassie");
+    let events = daemon.subscribe_events();
+
+    daemon
+        .fetch(cid, FetchOptions::default())
+        .read_to_end(&mut Vec::new())
+        .expect("cannot fetch CID using Lassie");
+
+    // Lassie delivers the events asynchronously, they may arrive after the retrieval finished
+    let mut kinds = Vec::new();
+    while !kinds.contains(&RetrievalEventKind::Finished) {
+        let event = events
+            .recv_timeout(Duration::from_secs(10))
+            .expect("cannot receive retrieval event");
+        kinds.push(event.kind);
+    }
+
+    assert_eq!(
+        kinds.first(),
+        Some(&RetrievalEventKind::Started { root: cid })
+    );
+    assert!(
+        kinds
+            .iter()
+            .any(|k| matches!(k, RetrievalEventKind::Succeeded { .. })),
+        "Expected a Succeeded event, actual events: {kinds:?}"
+    );
+
+    daemon.stop().expect("cannot stop Lassie");
+    // The channel is closed after the daemon stopped
+    let closed = loop {
+        if let Err(err) = events.recv_timeout(Duration::from_secs(1)) {
+            break err;
+        }
+    };
+    assert_eq!(closed, RecvTimeoutError::Disconnected);
+}
+
+#[cfg(feature = "tokio")]
+#[tokio::test(flavor = "multi_thread")]
+async fn start_daemon_and_fetch_cid_async() {
+    use tokio::io::AsyncReadExt;
+
+    setup_test_env();
+
+    let (provider, cid) = start_fixture_provider();
+    let daemon = Daemon::start_async(provider.daemon_config())
+        .await
+        .expect("cannot start Lassie");
+
+    let mut content = Vec::new();
+    daemon
+        .fetch_async(cid, FetchOptions::default())
+        .read_to_end(&mut content)
+        .await
+        .expect("cannot fetch CID using Lassie");
+
+    assert_eq!(
+        content,
+        include_bytes!("testdata/bafkreih25dih6ug3xtj73vswccw423b56ilrwmnos4cbwhrceudopdp5sq.car")
+    );
+
+    daemon.shutdown().await.expect("cannot stop Lassie");
+}
+
 #[test]
 fn configure_max_blocks() {
-    let _lock = setup_test_env();
+    setup_test_env();
+
+    let provider = TestProvider::start().expect("cannot start the test provider");
+    // A file with many blocks, the response is aborted once the block limit is reached
+    let cid = provider.add_file(&test_file_data(), 1024);
 
     let daemon = Daemon::start(DaemonConfig {
         max_blocks: Some(1),
-        ..DaemonConfig::default()
+        ..provider.daemon_config()
     })
     .expect("cannot start Lassie");
     let port = daemon.port();
     assert!(port > 0, "Lassie is listening on non-zero port number");
 
-    // XKCD Archives offered for exploration by IPFS Desktop
-    // This archive contains many blocks an takes long to download unless the block limit is applied
-    let url =
-        format!("http://127.0.0.1:{port}/ipfs/QmdmQXB2mzChmMeKY47C43LxUdg1NDJ5MWcKMKxDu7RgQm");
+    let url = format!("http://127.0.0.1:{port}/ipfs/{cid}");
     let response = ureq::get(&url).call();
     let response = assert_ok_response(response);
 
@@ -76,20 +261,22 @@ fn configure_max_blocks() {
 
 #[test]
 fn configure_global_timeout() {
-    let _lock = setup_test_env();
+    setup_test_env();
+
+    let provider = TestProvider::start().expect("cannot start the test provider");
+    // A file with many blocks served slowly, the retrieval cannot finish before the timeout
+    let cid = provider.add_file(&test_file_data(), 1024);
+    provider.set_block_delay(Duration::from_millis(200));
 
     let daemon = Daemon::start(DaemonConfig {
-        global_timeout: Some(Duration::from_millis(1000)),
-        ..DaemonConfig::default()
+        global_timeout: Some(Duration::from_secs(1)),
+        ..provider.daemon_config()
     })
     .expect("cannot start Lassie");
     let port = daemon.port();
     assert!(port > 0, "Lassie is listening on non-zero port number");
 
-    // XKCD Archives offered for exploration by IPFS Desktop
-    // This archive contains many blocks an takes long to download
-    let url =
-        format!("http://127.0.0.1:{port}/ipfs/QmdmQXB2mzChmMeKY47C43LxUdg1NDJ5MWcKMKxDu7RgQm");
+    let url = format!("http://127.0.0.1:{port}/ipfs/{cid}");
     let response = ureq::get(&url).call();
     let response = assert_ok_response(response);
 
@@ -104,19 +291,18 @@ fn configure_global_timeout() {
 
 #[test]
 fn it_rejects_anonymous_requests_when_configured_with_access_token() {
-    let _lock = setup_test_env();
+    setup_test_env();
 
+    let (provider, cid) = start_fixture_provider();
     let daemon = Daemon::start(DaemonConfig {
-        access_token: Some("super_secret".to_string()),
-        ..DaemonConfig::default()
+        access_tokens: vec![AccessToken::new("super_secret")],
+        ..provider.daemon_config()
     })
     .expect("cannot start Lassie");
     let port = daemon.port();
     assert!(port > 0, "Lassie is listening on non-zero port number");
 
-    let url = format!(
-        "http://127.0.0.1:{port}/ipfs/bafkreih25dih6ug3xtj73vswccw423b56ilrwmnos4cbwhrceudopdp5sq"
-    );
+    let url = format!("http://127.0.0.1:{port}/ipfs/{cid}");
     let response = ureq::get(&url)
         .set("Accept", "application/vnd.ipld.car")
         .call();
@@ -126,24 +312,23 @@ fn it_rejects_anonymous_requests_when_configured_with_access_token() {
 
 #[test]
 fn it_allows_authorized_requests_when_configured_with_access_token() {
-    let _lock = setup_test_env();
+    setup_test_env();
 
+    let (provider, cid) = start_fixture_provider();
     let daemon = Daemon::start(DaemonConfig {
-        access_token: Some("super_secret".to_string()),
-        ..DaemonConfig::default()
+        access_tokens: vec![AccessToken::new("super_secret")],
+        ..provider.daemon_config()
     })
     .expect("cannot start Lassie");
     let port = daemon.port();
     assert!(port > 0, "Lassie is listening on non-zero port number");
 
-    let url = format!(
-        "http://127.0.0.1:{port}/ipfs/bafkreih25dih6ug3xtj73vswccw423b56ilrwmnos4cbwhrceudopdp5sq"
-    );
+    let url = format!("http://127.0.0.1:{port}/ipfs/{cid}");
     let response = ureq::get(&url)
         .set("Accept", "application/vnd.ipld.car")
         .set(
             "Authorization",
-            &format!("Bearer {}", daemon.access_token().as_ref().unwrap()),
+            &format!("Bearer {}", daemon.access_token().unwrap()),
         )
         .call();
     assert_ok_response(response);
@@ -151,19 +336,18 @@ fn it_allows_authorized_requests_when_configured_with_access_token() {
 
 #[test]
 fn it_rejects_incorrect_authorization_when_configured_with_access_token() {
-    let _lock = setup_test_env();
+    setup_test_env();
 
+    let (provider, cid) = start_fixture_provider();
     let daemon = Daemon::start(DaemonConfig {
-        access_token: Some("super_secret".to_string()),
-        ..DaemonConfig::default()
+        access_tokens: vec![AccessToken::new("super_secret")],
+        ..provider.daemon_config()
     })
     .expect("cannot start Lassie");
     let port = daemon.port();
     assert!(port > 0, "Lassie is listening on non-zero port number");
 
-    let url = format!(
-        "http://127.0.0.1:{port}/ipfs/bafkreih25dih6ug3xtj73vswccw423b56ilrwmnos4cbwhrceudopdp5sq"
-    );
+    let url = format!("http://127.0.0.1:{port}/ipfs/{cid}");
     let response = ureq::get(&url)
         .set("Accept", "application/vnd.ipld.car")
         .set("Authorization", "Bearer wrong-token")
@@ -172,10 +356,197 @@ fn it_rejects_incorrect_authorization_when_configured_with_access_token() {
     assert_response_error(response, 401);
 }
 
-fn setup_test_env() -> MutexGuard<'static, ()> {
+#[test]
+fn it_responds_to_ping_without_access_token() {
+    setup_test_env();
+
+    let daemon = Daemon::start(DaemonConfig {
+        access_tokens: vec![AccessToken::new("super_secret")],
+        ..DaemonConfig::default()
+    })
+    .expect("cannot start Lassie");
+    let port = daemon.port();
+
+    let response = assert_ok_response(ureq::get(&format!("http://127.0.0.1:{port}/ping")).call());
+    assert_eq!(response.into_string().unwrap(), "pong");
+}
+
+#[test]
+fn it_accepts_rotated_access_tokens() {
+    setup_test_env();
+
+    let (provider, cid) = start_fixture_provider();
+    let daemon = Daemon::start(DaemonConfig {
+        access_tokens: vec![AccessToken::new("old_secret")],
+        ..provider.daemon_config()
+    })
+    .expect("cannot start Lassie");
+    let url = format!("http://127.0.0.1:{}/ipfs/{cid}", daemon.port());
+    let status_with_token = |token: &str| {
+        let response = ureq::get(&url)
+            .set("Accept", "application/vnd.ipld.car")
+            .set("Authorization", &format!("Bearer {token}"))
+            .call();
+        match response {
+            Ok(response) => response.status(),
+            Err(ureq::Error::Status(code, _)) => code,
+            Err(err) => panic!("Request failed with unexpected error: {err:?}"),
+        }
+    };
+
+    daemon
+        .set_access_tokens(vec![
+            AccessToken::new("old_secret"),
+            AccessToken::new("new_secret"),
+        ])
+        .expect("cannot add the new access token");
+    assert_eq!(status_with_token("old_secret"), 200);
+    assert_eq!(status_with_token("new_secret"), 200);
+
+    daemon
+        .set_access_tokens(vec![AccessToken::new("new_secret")])
+        .expect("cannot remove the old access token");
+    assert_eq!(status_with_token("old_secret"), 401);
+    assert_eq!(status_with_token("new_secret"), 200);
+    assert_eq!(daemon.access_tokens(), vec![AccessToken::new("new_secret")]);
+}
+
+#[test]
+fn it_rate_limits_requests_per_access_token() {
+    setup_test_env();
+
+    let (provider, cid) = start_fixture_provider();
+    let policy = AccessTokenPolicy {
+        requests_per_minute: Some(1),
+        ..AccessTokenPolicy::default()
+    };
+    let daemon = Daemon::start(DaemonConfig {
+        access_tokens: vec![
+            AccessToken::new("limited").with_policy(policy),
+            AccessToken::new("unlimited"),
+        ],
+        ..provider.daemon_config()
+    })
+    .expect("cannot start Lassie");
+    let url = format!("http://127.0.0.1:{}/ipfs/{cid}", daemon.port());
+    // Returns the response for all status codes
+    let request = |token: &str| {
+        let response = ureq::get(&url)
+            .set("Accept", "application/vnd.ipld.car")
+            .set("Authorization", &format!("Bearer {token}"))
+            .call();
+        match response {
+            Ok(response) | Err(ureq::Error::Status(_, response)) => response,
+            Err(err) => panic!("Request failed with unexpected error: {err:?}"),
+        }
+    };
+
+    let response = request("limited");
+    assert_eq!(response.status(), 200);
+    let mut content = Vec::new();
+    response
+        .into_reader()
+        .read_to_end(&mut content)
+        .expect("cannot read response body");
+
+    let response = request("limited");
+    assert_eq!(response.status(), 429);
+    let retry_after: u64 = response
+        .header("Retry-After")
+        .expect("Retry-After header")
+        .parse()
+        .expect("Retry-After in seconds");
+    assert!(
+        (1..=60).contains(&retry_after),
+        "unexpected Retry-After: {retry_after}"
+    );
+    assert_eq!(request("unlimited").status(), 200);
+
+    let usage = daemon
+        .access_token_usage("limited")
+        .expect("usage of a configured token");
+    assert_eq!(usage.active_retrievals, 0);
+    assert_eq!(usage.requests_last_minute, 1);
+    assert_eq!(usage.bytes_today, content.len() as u64);
+    assert_eq!(daemon.access_token_usage("unknown"), None);
+}
+
+#[test]
+fn fetch_from_test_provider() {
+    setup_test_env();
+
+    let (provider, cid) = start_fixture_provider();
+    let daemon = provider.start_daemon().expect("cannot start Lassie");
+
+    let mut content = Vec::new();
+    daemon
+        .fetch(cid, FetchOptions::default())
+        .read_to_end(&mut content)
+        .expect("cannot fetch CID from the test provider");
+
+    assert_eq!(
+        content,
+        include_bytes!("testdata/bafkreih25dih6ug3xtj73vswccw423b56ilrwmnos4cbwhrceudopdp5sq.car")
+    );
+}
+
+#[test]
+fn serves_cached_blocks_without_providers() {
+    use lassie::CacheConfig;
+
+    setup_test_env();
+
+    let cache_dir = std::env::temp_dir().join(format!("rusty-lassie-cache-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&cache_dir);
+
+    let (provider, cid) = start_fixture_provider();
+    let daemon = Daemon::start(DaemonConfig {
+        cache: Some(CacheConfig {
+            path: cache_dir.clone(),
+            max_size: 1024 * 1024,
+        }),
+        ..provider.daemon_config()
+    })
+    .expect("cannot start Lassie");
+
+    let mut first = Vec::new();
+    daemon
+        .fetch(cid, FetchOptions::default())
+        .read_to_end(&mut first)
+        .expect("cannot fetch CID from the test provider");
+
+    // The provider is gone, the second retrieval must be served from the cache
+    drop(provider);
+    let mut second = Vec::new();
+    daemon
+        .fetch(cid, FetchOptions::default())
+        .read_to_end(&mut second)
+        .expect("cannot fetch CID from the cache");
+    assert_eq!(second, first);
+
+    drop(daemon);
+    std::fs::remove_dir_all(&cache_dir).unwrap();
+}
+
+fn start_fixture_provider() -> (TestProvider, Cid) {
+    let provider = TestProvider::start().expect("cannot start the test provider");
+    let roots = provider
+        .add_car(
+            &include_bytes!(
+                "testdata/bafkreih25dih6ug3xtj73vswccw423b56ilrwmnos4cbwhrceudopdp5sq.car"
+            )[..],
+        )
+        .expect("cannot load the CAR fixture");
+    (provider, roots[0])
+}
+
+fn test_file_data() -> Vec<u8> {
+    // 251 is a prime, the chunks of this data are all different
+    (0..251u8).cycle().take(256 * 1024).collect()
+}
+
+fn setup_test_env() {
     let _ = env_logger::builder().is_test(true).try_init();
-    let lock = TEST_GUARD.lock().expect("cannot obtain global test lock. This typically happens when one of the test fails; the problem should go away after you fix the test failure.");
-    lock
 }
 
 fn assert_ok_response(response: Result<ureq::Response, ureq::Error>) -> ureq::Response {